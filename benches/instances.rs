@@ -0,0 +1,48 @@
+//! Building the wgpu renderer's per-frame instance data for a large scene.
+//!
+//! Run with `cargo bench --features bench --bench instances`.
+
+use cgmath::Vector3;
+use criterion::{criterion_group, criterion_main, Criterion};
+use hewn::scene::{Components, Entity, PositionComponent, RenderComponent, Scene, SizeComponent};
+use hewn::wgpu::render::bench::InstanceBuilder;
+use hewn::wgpu::render::RenderMode;
+
+const ENTITIES: usize = 10_000;
+
+fn scene() -> Scene {
+    let mut scene = Scene::new();
+    for i in 0..ENTITIES {
+        scene.add_entity_from_components(Components {
+            position: Some(PositionComponent {
+                x: (i % 100) as f32,
+                y: (i / 100) as f32,
+            }),
+            render: Some(RenderComponent {
+                ascii_character: '#',
+                rgb: Vector3::new(0.5, 0.5, 0.5),
+                layer: 0,
+            }),
+            size: Some(SizeComponent { x: 1.0, y: 1.0 }),
+            ..Components::new()
+        });
+    }
+    scene
+}
+
+fn rebuild_instances(c: &mut Criterion) {
+    let scene = scene();
+    let entities: Vec<&Entity> = scene.entities().iter().collect();
+    let mut instances = InstanceBuilder::default();
+    for (name, mode) in [
+        ("blocks", RenderMode::Blocks),
+        ("glyphs", RenderMode::Glyphs),
+    ] {
+        c.bench_function(&format!("rebuild {ENTITIES} instances ({name})"), |b| {
+            b.iter(|| instances.rebuild(&entities, mode))
+        });
+    }
+}
+
+criterion_group!(benches, rebuild_instances);
+criterion_main!(benches);
@@ -18,7 +18,7 @@ const WALL_HEIGHT: u16 = 5;
 const END_Y_POS: f32 = -10.0;
 
 pub fn create_game(seed: Option<u64>) -> Game {
-    let mut game = Game::new(WIDTH as u16, HEIGHT as u16, seed);
+    let mut game = Game::new(WIDTH, HEIGHT, seed);
     game.initialise_walls();
     game.initialise_player();
     game
@@ -73,8 +73,10 @@ impl Game {
             render: Some(RenderComponent {
                 ascii_character: '#',
                 rgb: (0.0, 0.0, 0.0).into(),
+                layer: 1,
             }),
             camera_follow: Some(CameraFollow {}),
+            sprite: None,
         };
         let id = self.scene.add_entity_from_components(components);
         self.player_id = id;
@@ -89,8 +91,10 @@ impl Game {
                 render: Some(RenderComponent {
                     ascii_character: '\\',
                     rgb: (0.0, 0.0, 0.5).into(),
+                    layer: 0,
                 }),
                 camera_follow: None,
+                sprite: None,
             };
             let id = self.scene.add_entity_from_components(components);
             self.wall_ids.insert(id);
@@ -120,7 +124,7 @@ impl Game {
                 let y = self
                     .rng
                     .gen_range(0..(self.height.saturating_sub(WALL_HEIGHT)));
-                for yy in y..(y + WALL_HEIGHT as u16) {
+                for yy in y..(y + WALL_HEIGHT) {
                     walls.push((index as f32, yy as f32));
                 }
                 last_wall = 0;
@@ -190,6 +194,10 @@ impl GameHandler for Game {
         &self.scene
     }
 
+    fn scene_mut(&mut self) -> Option<&mut Scene> {
+        Some(&mut self.scene)
+    }
+
     fn debug_str(&self) -> Option<String> {
         if let Some(player) = self.scene.get_entity_by_id(self.player_id) {
             let pos = player.components.position.as_ref()?;
@@ -226,3 +234,22 @@ impl GameHandler for Game {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initial_scene_round_trips_through_serde() {
+        let mut game = create_game(Some(42));
+        let json = serde_json::to_string(game.scene()).unwrap();
+        let mut loaded: Scene = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.entities(), game.scene().entities());
+
+        let scene = game.scene_mut().unwrap();
+        assert_eq!(
+            loaded.add_entity_from_components(Components::new()),
+            scene.add_entity_from_components(Components::new())
+        );
+    }
+}
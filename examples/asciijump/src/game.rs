@@ -1,3 +1,4 @@
+use hewn::runtime::save::SaveSlots;
 use hewn::runtime::GameHandler;
 use hewn::runtime::Key;
 use hewn::scene::{
@@ -13,6 +14,9 @@ pub const WIDTH: f32 = 10.0;
 pub const HEIGHT: f32 = 500.0;
 pub const SCREEN_WIDTH: u16 = 10;
 pub const SCREEN_HEIGHT: u16 = 20;
+/// Version of the save format, bumped with a migration when it changes.
+pub const SAVE_VERSION: u32 = 1;
+const HIGH_SCORE_SLOT: &str = "high-score";
 
 pub fn create_game(seed: Option<u64>) -> Game {
     let mut game = Game::new(WIDTH, HEIGHT, seed);
@@ -33,10 +37,12 @@ pub struct Game {
     pub height: f32,
     pub state: GameState,
     pub score: u16,
+    pub high_score: u16,
     pub player_id: EntityId,
 
     rng: Box<dyn RngCore>,
     scene: Scene,
+    saves: Option<SaveSlots>,
     platform_ids: HashSet<EntityId>,
 }
 
@@ -52,7 +58,9 @@ impl Game {
             height,
             state: GameState::Menu,
             score: 0,
+            high_score: 0,
             scene: Scene::new(),
+            saves: None,
             player_id: EntityId(0),
             platform_ids: HashSet::new(),
             rng,
@@ -61,6 +69,28 @@ impl Game {
 
     pub fn end_game(&mut self) {
         self.state = GameState::Lost(self.score);
+        self.record_high_score();
+    }
+
+    /// Keep the high score in `saves`, starting from the one saved there.
+    pub fn use_saves(&mut self, saves: SaveSlots) {
+        match saves.load::<u16>(HIGH_SCORE_SLOT) {
+            Ok(save) => self.high_score = save.map_or(0, |save| save.data),
+            Err(e) => log::error!("Unable to load high score: {:#}", e),
+        }
+        self.saves = Some(saves);
+    }
+
+    fn record_high_score(&mut self) {
+        if self.score <= self.high_score {
+            return;
+        }
+        self.high_score = self.score;
+        if let Some(saves) = &mut self.saves {
+            if let Err(e) = saves.save(HIGH_SCORE_SLOT, None, &self.high_score) {
+                log::error!("Unable to save high score: {:#}", e);
+            }
+        }
     }
 
     pub fn initialise_player(&mut self) {
@@ -71,8 +101,10 @@ impl Game {
             render: Some(RenderComponent {
                 ascii_character: '#',
                 rgb: (0.0, 0.0, 0.0).into(),
+                layer: 1,
             }),
             camera_follow: Some(CameraFollow {}),
+            sprite: None,
         };
         let id = self.scene.add_entity_from_components(components);
         self.player_id = id;
@@ -87,8 +119,10 @@ impl Game {
                 render: Some(RenderComponent {
                     ascii_character: '=',
                     rgb: (0.0, 0.0, 0.5).into(),
+                    layer: 0,
                 }),
                 camera_follow: None,
+                sprite: None,
             };
             let id = self.scene.add_entity_from_components(components);
             self.platform_ids.insert(id);
@@ -189,11 +223,22 @@ impl GameHandler for Game {
         &self.scene
     }
 
+    fn scene_mut(&mut self) -> Option<&mut Scene> {
+        Some(&mut self.scene)
+    }
+
+    fn on_exit(&mut self) {
+        self.record_high_score();
+    }
+
     fn debug_str(&self) -> Option<String> {
         if let Some(player) = self.scene.get_entity_by_id(self.player_id) {
             let pos = player.components.position.as_ref()?;
             let vel = player.components.velocity.as_ref()?;
-            Some(format!("v = {:4}, x = {:3}, y = {:3}", vel.y, pos.x, pos.y))
+            Some(format!(
+                "v = {:4}, x = {:3}, y = {:3}, best = {:3}",
+                vel.y, pos.x, pos.y, self.high_score
+            ))
         } else {
             None
         }
@@ -233,13 +278,14 @@ impl GameHandler for Game {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use hewn::runtime::recording::{InputEvent, InputRecorder};
     use hewn::runtime::GameHandler;
     use hewn::scene::ComponentType;
 
-    fn get_player_entity<'a>(game: &'a Game) -> &'a hewn::scene::Entity {
+    fn get_player_entity(game: &Game) -> &hewn::scene::Entity {
         let scene = game.scene();
         let mut tracked = scene.get_entities_with_component(ComponentType::CameraFollow);
-        assert!(tracked.len() > 0, "player entity not found");
+        assert!(!tracked.is_empty(), "player entity not found");
         tracked.remove(0)
     }
 
@@ -292,4 +338,54 @@ mod tests {
         }
         assert!(bounced, "expected to bounce on the platform when falling");
     }
+
+    #[test]
+    fn replay_reproduces_seeded_run() {
+        let dt = Duration::from_millis(16);
+        let mut recorder = InputRecorder::new("unused.rec");
+        let mut game = create_game(Some(7));
+
+        for frame in 0..300 {
+            let event = match frame % 40 {
+                0 => Some(InputEvent::StartGame),
+                5 => Some(InputEvent::Key {
+                    key: Key::Left,
+                    pressed: true,
+                }),
+                20 => Some(InputEvent::Key {
+                    key: Key::Left,
+                    pressed: false,
+                }),
+                _ => None,
+            };
+            if let Some(event) = event {
+                recorder.record(event);
+                event.apply(&mut game);
+            }
+            game.next(dt);
+            recorder.end_frame(dt);
+        }
+
+        let mut replayed = create_game(Some(7));
+        recorder.recording().replay(&mut replayed);
+
+        let original = get_player_entity(&game).components.position.unwrap();
+        let copy = get_player_entity(&replayed).components.position.unwrap();
+        assert_eq!((original.x, original.y), (copy.x, copy.y));
+        assert_eq!(game.score, replayed.score);
+    }
+
+    #[test]
+    fn initial_scene_round_trips_through_serde() {
+        let mut game = create_game(Some(42));
+        let json = serde_json::to_string(game.scene()).unwrap();
+        let mut loaded: Scene = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.entities(), game.scene().entities());
+
+        let scene = game.scene_mut().unwrap();
+        assert_eq!(
+            loaded.add_entity_from_components(Components::new()),
+            scene.add_entity_from_components(Components::new())
+        );
+    }
 }
@@ -1,4 +1,5 @@
-use asciijump::game::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use asciijump::game::{SAVE_VERSION, SCREEN_HEIGHT, SCREEN_WIDTH};
+use hewn::runtime::save::SaveSlots;
 #[cfg(not(target_arch = "wasm32"))]
 use hewn::terminal::runtime::TerminalRuntime;
 use hewn::wgpu;
@@ -16,12 +17,14 @@ fn main() {
 #[cfg(not(target_arch = "wasm32"))]
 pub fn play_asciijump_in_terminal() {
     let mut game = create_game(None);
+    game.use_saves(SaveSlots::for_game("asciijump", SAVE_VERSION));
     let mut runtime = TerminalRuntime::new(SCREEN_WIDTH, SCREEN_HEIGHT);
     runtime.start(&mut game);
 }
 
 pub fn play_asciijump_in_wgpu() {
     let mut game = create_game(None);
+    game.use_saves(SaveSlots::for_game("asciijump", SAVE_VERSION));
     let mut runtime = wgpu::runtime::WindowRuntime::new();
     let player_entity_id = game.player_id;
     let _ = runtime.start(
@@ -1,11 +1,9 @@
-use cgmath;
+use hewn::runtime::save::SaveSlots;
 use hewn::runtime::{GameHandler, Key};
-use hewn::scene::{
-    CameraFollow, EntityId, PositionComponent, RenderComponent, SizeComponent, VelocityComponent,
-};
+use hewn::scene::{CameraFollow, EntityId, PositionComponent, RenderComponent, SizeComponent};
 use hewn::scene::{Components, Scene};
 use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 use std::time::Duration;
 
 pub fn create_game(width: u16, height: u16, seed: Option<u64>) -> Game {
@@ -37,11 +35,15 @@ pub struct Game {
     pub width: u16,
     pub height: u16,
     pub score: u16,
+    pub high_score: u16,
 
     state: GameState,
     scene: Scene,
+    saves: Option<SaveSlots>,
     player_id: EntityId,
     player_direction: Direction,
+    /// Turns pressed but not yet taken, one per move, so quick turns aren't lost.
+    queued_directions: VecDeque<Direction>,
     body_ids: Vec<EntityId>,
     wall_ids: HashSet<EntityId>,
     food_id: Option<EntityId>,
@@ -52,6 +54,12 @@ pub struct Game {
     move_interval: f32,
 }
 
+/// Most turns queued ahead of the snake.
+const MAX_QUEUED_TURNS: usize = 3;
+/// Version of the save format, bumped with a migration when it changes.
+pub const SAVE_VERSION: u32 = 1;
+const HIGH_SCORE_SLOT: &str = "high-score";
+
 impl Game {
     fn compute_next_direction(current: Direction, key: Option<Key>) -> Direction {
         let Some(key) = key else { return current };
@@ -88,9 +96,12 @@ impl Game {
             height,
             state: GameState::Menu,
             score: 0,
+            high_score: 0,
             scene: Scene::new(),
+            saves: None,
             player_id: EntityId(0),
             player_direction: Direction::Up,
+            queued_directions: VecDeque::new(),
             body_ids: vec![],
             wall_ids: HashSet::new(),
             food_id: None,
@@ -112,12 +123,15 @@ impl Game {
                     y: 0.0,
                     z: 0.0,
                 },
+                layer: 1,
             }),
             camera_follow: Some(CameraFollow {}),
+            sprite: None,
         };
         let id = self.scene.add_entity_from_components(components);
         self.player_id = id;
         self.player_direction = Direction::Up;
+        self.queued_directions.clear();
     }
 
     pub fn add_walls_from_positions(&mut self, walls: Vec<(f32, f32)>) {
@@ -133,8 +147,10 @@ impl Game {
                         y: 0.1,
                         z: 0.0,
                     },
+                    layer: 0,
                 }),
                 camera_follow: None,
+                sprite: None,
             };
             let id = self.scene.add_entity_from_components(components);
             self.wall_ids.insert(id);
@@ -183,8 +199,10 @@ impl Game {
                     y: 0.0,
                     z: 0.0,
                 },
+                layer: 0,
             }),
             camera_follow: None,
+            sprite: None,
         };
         let id = self.scene.add_entity_from_components(components);
         self.food_id = Some(id);
@@ -257,8 +275,10 @@ impl Game {
                     y: 0.0,
                     z: 0.1,
                 },
+                layer: 0,
             }),
             camera_follow: None,
+            sprite: None,
         };
         let id = self.scene.add_entity_from_components(components);
         self.body_ids.push(id);
@@ -299,6 +319,28 @@ impl Game {
 
     pub fn end_game(&mut self) {
         self.state = GameState::Lost(self.score);
+        self.record_high_score();
+    }
+
+    /// Keep the high score in `saves`, starting from the one saved there.
+    pub fn use_saves(&mut self, saves: SaveSlots) {
+        match saves.load::<u16>(HIGH_SCORE_SLOT) {
+            Ok(save) => self.high_score = save.map_or(0, |save| save.data),
+            Err(e) => log::error!("Unable to load high score: {:#}", e),
+        }
+        self.saves = Some(saves);
+    }
+
+    fn record_high_score(&mut self) {
+        if self.score <= self.high_score {
+            return;
+        }
+        self.high_score = self.score;
+        if let Some(saves) = &mut self.saves {
+            if let Err(e) = saves.save(HIGH_SCORE_SLOT, None, &self.high_score) {
+                log::error!("Unable to save high score: {:#}", e);
+            }
+        }
     }
 }
 
@@ -313,12 +355,20 @@ impl GameHandler for Game {
             }
         }
         self.player_direction = Direction::Up;
+        self.queued_directions.clear();
         self.state = GameState::InGame;
     }
 
     fn handle_key(&mut self, key: Key, pressed: bool) -> bool {
         if pressed {
-            self.player_direction = Game::compute_next_direction(self.player_direction, Some(key));
+            let current = *self
+                .queued_directions
+                .back()
+                .unwrap_or(&self.player_direction);
+            let next = Game::compute_next_direction(current, Some(key));
+            if next != current && self.queued_directions.len() < MAX_QUEUED_TURNS {
+                self.queued_directions.push_back(next);
+            }
         }
         true
     }
@@ -334,6 +384,9 @@ impl GameHandler for Game {
         // Only move when timer reaches the interval
         if self.move_timer >= self.move_interval {
             self.move_timer = 0.0; // Reset timer
+            if let Some(direction) = self.queued_directions.pop_front() {
+                self.player_direction = direction;
+            }
 
             // Store previous positions for body segments
             let mut prev_positions: Vec<(f32, f32)> = Vec::with_capacity(self.body_ids.len() + 1);
@@ -412,12 +465,21 @@ impl GameHandler for Game {
         &self.scene
     }
 
+    fn scene_mut(&mut self) -> Option<&mut Scene> {
+        Some(&mut self.scene)
+    }
+
+    fn on_exit(&mut self) {
+        self.record_high_score();
+    }
+
     fn debug_str(&self) -> Option<String> {
         if let Some(head) = self.scene.get_entity_by_id(self.player_id) {
             let pos = head.components.position.as_ref()?;
             Some(format!(
-                "len = {:3}, x = {:3}, y = {:3}, dir = {:?}",
+                "len = {:3}, best = {:3}, x = {:3}, y = {:3}, dir = {:?}",
                 1 + self.body_ids.len(),
+                self.high_score,
                 pos.x,
                 pos.y,
                 self.player_direction
@@ -440,3 +502,22 @@ fn generate_walls_positions(width: u16, height: u16) -> Vec<(f32, f32)> {
     }
     walls
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initial_scene_round_trips_through_serde() {
+        let mut game = create_game(20, 10, Some(42));
+        let json = serde_json::to_string(game.scene()).unwrap();
+        let mut loaded: Scene = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.entities(), game.scene().entities());
+
+        let scene = game.scene_mut().unwrap();
+        assert_eq!(
+            loaded.add_entity_from_components(Components::new()),
+            scene.add_entity_from_components(Components::new())
+        );
+    }
+}
@@ -1,12 +1,14 @@
 pub mod game;
 
-use crate::game::create_game;
+use crate::game::{create_game, SAVE_VERSION};
+use hewn::runtime::save::SaveSlots;
 use hewn::runtime::GameHandler;
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
 pub fn run_in_canvas(width: u16, height: u16, seed: Option<u64>) {
     let mut game = create_game(width, height, seed);
+    game.use_saves(SaveSlots::for_game("snake", SAVE_VERSION));
     game.start_game();
     let mut runtime = hewn::wgpu::runtime::WindowRuntime::new();
     let _ = runtime.start(&mut game, hewn::wgpu::render::CameraStrategy::AllEntities);
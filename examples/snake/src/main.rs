@@ -1,5 +1,6 @@
 mod game;
-use crate::game::create_game;
+use crate::game::{create_game, SAVE_VERSION};
+use hewn::runtime::save::SaveSlots;
 #[cfg(not(target_arch = "wasm32"))]
 use hewn::runtime::GameHandler;
 
@@ -8,6 +9,7 @@ const SCREEN_HEIGHT: u16 = 50;
 
 fn main() {
     let mut game = create_game(SCREEN_WIDTH, SCREEN_HEIGHT, None);
+    game.use_saves(SaveSlots::for_game("snake", SAVE_VERSION));
     game.start_game();
     let mut runtime = hewn::wgpu::runtime::WindowRuntime::new();
     let _ = runtime.start(&mut game, hewn::wgpu::render::CameraStrategy::AllEntities);
@@ -1,4 +1,3 @@
-use cgmath;
 use hewn::scene::{Components, EntityId, PositionComponent, RenderComponent, SizeComponent};
 use hewn::wgpu::runtime::WindowRuntime;
 use hewn::{runtime::GameHandler, scene::Scene};
@@ -63,10 +62,12 @@ impl HelloGame {
                     y: 0.0,
                     z: 1.0,
                 },
+                layer: 1,
             }),
             velocity: Some(VelocityComponent { x: 0.0, y: 0.0 }),
             size: Some(SizeComponent { x: 2.0, y: 1.0 }),
             camera_follow: None,
+            sprite: None,
         });
         scene.add_entity_from_components(Components {
             position: Some(PositionComponent { x: 8.0, y: 5.0 }),
@@ -77,10 +78,12 @@ impl HelloGame {
                     y: 0.0,
                     z: 0.0,
                 },
+                layer: 0,
             }),
             velocity: None,
             size: Some(SizeComponent { x: 2.0, y: 1.0 }),
             camera_follow: None,
+            sprite: None,
         });
 
         Self {
@@ -144,6 +147,10 @@ impl GameHandler for HelloGame {
         &self.scene
     }
 
+    fn scene_mut(&mut self) -> Option<&mut Scene> {
+        Some(&mut self.scene)
+    }
+
     fn debug_str(&self) -> Option<String> {
         let player = self.scene.get_entity_by_id(self.player_id)?;
         let pos = player.components.position.as_ref()?;
@@ -160,3 +167,22 @@ fn main() {
         hewn::wgpu::render::CameraStrategy::CameraFollow(entity_id),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initial_scene_round_trips_through_serde() {
+        let mut game = HelloGame::new();
+        let json = serde_json::to_string(game.scene()).unwrap();
+        let mut loaded: Scene = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.entities(), game.scene().entities());
+
+        let scene = game.scene_mut().unwrap();
+        assert_eq!(
+            loaded.add_entity_from_components(Components::new()),
+            scene.add_entity_from_components(Components::new())
+        );
+    }
+}
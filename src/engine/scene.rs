@@ -1,20 +1,26 @@
 use std::time::Duration;
 
 use cgmath::Vector3;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Entity {
     pub id: EntityId,
     pub components: Components,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct Components {
     pub position: Option<PositionComponent>,
     pub velocity: Option<VelocityComponent>,
     pub render: Option<RenderComponent>,
     pub size: Option<SizeComponent>,
     pub camera_follow: Option<CameraFollow>,
+    pub sprite: Option<SpriteComponent>,
 }
 
 impl Components {
@@ -25,11 +31,13 @@ impl Components {
             render: None,
             size: None,
             camera_follow: None,
+            sprite: None,
         }
     }
 }
 
 #[derive(PartialEq, Debug, Eq, Hash, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EntityId(pub u16);
 
 impl Entity {
@@ -60,26 +68,45 @@ impl Entity {
                 render: ascii_character.map(|c| RenderComponent {
                     ascii_character: c,
                     rgb: Vector3::new(0.0, 0.0, 0.0),
+                    layer: 0,
                 }),
                 camera_follow: if track { Some(CameraFollow {}) } else { None },
+                sprite: None,
             },
         }
     }
 }
 
+impl Entity {
+    /// The layer the entity is drawn on, or 0 if it has no `RenderComponent`.
+    pub fn layer(&self) -> i16 {
+        self.components.render.map_or(0, |render| render.layer)
+    }
+}
+
+/// Sort entities into draw order, lowest layer first. The sort is stable, and an already
+/// sorted scene (the usual case) is only checked, not sorted.
+pub fn sort_by_layer(entities: &mut [&Entity]) {
+    if !entities.is_sorted_by_key(|e| e.layer()) {
+        entities.sort_by_key(|e| e.layer());
+    }
+}
+
 pub enum ComponentType {
     Position,
     Velocity,
     Render,
     Size,
     CameraFollow,
+    Sprite,
 }
 
 #[allow(dead_code)]
 trait Component {
     const TYPE: ComponentType;
 }
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PositionComponent {
     pub x: f32,
     pub y: f32,
@@ -98,7 +125,8 @@ impl From<(f32, f32)> for PositionComponent {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct VelocityComponent {
     pub x: f32,
     pub y: f32,
@@ -107,7 +135,8 @@ impl Component for VelocityComponent {
     const TYPE: ComponentType = ComponentType::Velocity;
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SizeComponent {
     pub x: f32,
     pub y: f32,
@@ -116,25 +145,84 @@ impl Component for SizeComponent {
     const TYPE: ComponentType = ComponentType::Size;
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RenderComponent {
     pub ascii_character: char,
     pub rgb: Vector3<f32>,
+    /// Draw order: higher layers are drawn over lower ones, and entities on the same layer
+    /// are drawn in the order they were added.
+    pub layer: i16,
 }
 impl Component for RenderComponent {
     const TYPE: ComponentType = ComponentType::Render;
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CameraFollow {}
 impl Component for CameraFollow {
     const TYPE: ComponentType = ComponentType::CameraFollow;
 }
 
+/// Identifies a texture atlas loaded by the window runtime, in the order atlases were added.
+#[derive(PartialEq, Debug, Eq, Hash, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AtlasId(pub u16);
+
+/// Draw the entity with a region of a texture atlas instead of a flat colour. The region is
+/// in pixels from the atlas's top-left corner and is stretched over the entity's size.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SpriteComponent {
+    pub atlas: AtlasId,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+impl Component for SpriteComponent {
+    const TYPE: ComponentType = ComponentType::Sprite;
+}
+
+/// With the `serde` feature, a scene serializes its entities and the next entity id, so a
+/// loaded scene keeps allocating ids after the saved ones, even if the saved next id is
+/// missing or behind them.
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "SavedScene"))]
 pub struct Scene {
     next_entity_id: EntityId,
     entities: Vec<Entity>,
+    /// Pairs from the latest collision pass, kept for the debug overlay.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    last_collisions: Vec<[EntityId; 2]>,
+}
+
+/// A scene as it was saved, before its next entity id is checked against its entities.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct SavedScene {
+    #[serde(default)]
+    next_entity_id: EntityId,
+    entities: Vec<Entity>,
+}
+
+#[cfg(feature = "serde")]
+impl From<SavedScene> for Scene {
+    fn from(saved: SavedScene) -> Scene {
+        let after_entities = saved
+            .entities
+            .iter()
+            .map(|e| e.id.0.saturating_add(1))
+            .max()
+            .unwrap_or(0);
+        Scene {
+            next_entity_id: EntityId(saved.next_entity_id.0.max(after_entities)),
+            entities: saved.entities,
+            last_collisions: vec![],
+        }
+    }
 }
 
 impl Scene {
@@ -151,16 +239,22 @@ impl Scene {
             };
 
             if velocity.x != 0.0 {
-                position.x = position.x + velocity.x * dt.as_secs_f32();
+                position.x += velocity.x * dt.as_secs_f32();
             }
             if velocity.y != 0.0 {
-                position.y = position.y + velocity.y * dt.as_secs_f32();
+                position.y += velocity.y * dt.as_secs_f32();
             }
         }
     }
 
-    pub fn collision_pass(&self, dt: Duration) -> Vec<[EntityId; 2]> {
-        collisions::collision_pass(&self.entities, dt)
+    pub fn collision_pass(&mut self, dt: Duration) -> Vec<[EntityId; 2]> {
+        self.last_collisions = collisions::collision_pass(&self.entities, dt);
+        self.last_collisions.clone()
+    }
+
+    /// The pairs the latest [`Scene::collision_pass`] returned.
+    pub fn last_collisions(&self) -> &[[EntityId; 2]] {
+        &self.last_collisions
     }
 }
 
@@ -169,6 +263,7 @@ impl Scene {
         Scene {
             entities: vec![],
             next_entity_id: EntityId(0),
+            last_collisions: vec![],
         }
     }
 
@@ -183,6 +278,16 @@ impl Scene {
         new_entity_id
     }
 
+    /// Remove an entity, returning it if it was in the scene. Its id is not reused.
+    pub fn remove_entity(&mut self, id: EntityId) -> Option<Entity> {
+        let index = self.entities.iter().position(|e| e.id == id)?;
+        Some(self.entities.remove(index))
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
     pub fn get_entity_by_id(&self, id: EntityId) -> Option<&Entity> {
         self.entities.iter().find(|e| e.id == id)
     }
@@ -226,6 +331,12 @@ impl Scene {
                     }
                     false
                 }
+                ComponentType::Sprite => {
+                    if e.components.sprite.is_some() {
+                        return true;
+                    }
+                    false
+                }
             })
             .collect::<Vec<&Entity>>();
         entities
@@ -266,6 +377,12 @@ impl Scene {
                     }
                     false
                 }
+                ComponentType::Sprite => {
+                    if e.components.sprite.is_some() {
+                        return true;
+                    }
+                    false
+                }
             })
             .collect::<Vec<&mut Entity>>();
         entities
@@ -276,8 +393,9 @@ pub mod collisions {
     use crate::scene::{Entity, EntityId, VelocityComponent};
     use std::{ops::Range, time::Duration};
 
+    /// The area an entity covers over a step of `dt`, including how far it moves.
     #[derive(Debug, PartialEq)]
-    struct CollisionBox {
+    pub(crate) struct CollisionBox {
         pub x: Range<f32>,
         pub y: Range<f32>,
     }
@@ -522,6 +640,7 @@ mod test {
             render: None,
             size: None,
             camera_follow: None,
+            sprite: None,
         }
     }
 
@@ -560,6 +679,7 @@ mod test {
             render: None,
             size: None,
             camera_follow: None,
+            sprite: None,
         });
         let entity_two_id = scene.add_entity_from_components(Components {
             position: Some(PositionComponent { x: 1.0, y: 1.0 }),
@@ -567,6 +687,7 @@ mod test {
             render: None,
             size: None,
             camera_follow: None,
+            sprite: None,
         });
         assert_eq!(scene.entities.len(), 2);
 
@@ -603,6 +724,7 @@ mod test {
             render: None,
             size: None,
             camera_follow: None,
+            sprite: None,
         });
         let entity_two_id = scene.add_entity_from_components(Components {
             position: Some(PositionComponent { x: 1.0, y: 1.0 }),
@@ -610,6 +732,7 @@ mod test {
             render: None,
             size: None,
             camera_follow: None,
+            sprite: None,
         });
         assert_eq!(scene.entities.len(), 2);
 
@@ -659,4 +782,89 @@ mod test {
         assert_eq!(entity_position.x, 2.0);
         assert_eq!(entity_position.y, 2.0);
     }
+
+    #[test]
+    fn test_sort_by_layer_is_stable() {
+        let with_layer = |id: u16, layer: i16| {
+            let mut entity = Entity::from_tuples(
+                EntityId(id),
+                (0.0, 0.0),
+                (0.0, 0.0),
+                (1.0, 1.0),
+                Some('#'),
+                false,
+            );
+            entity.components.render.as_mut().unwrap().layer = layer;
+            entity
+        };
+        let entities = [
+            with_layer(0, 1),
+            with_layer(1, 0),
+            with_layer(2, -1),
+            with_layer(3, 0),
+            Entity::new(EntityId(4)),
+        ];
+        let mut sorted: Vec<&Entity> = entities.iter().collect();
+        sort_by_layer(&mut sorted);
+
+        let ids: Vec<u16> = sorted.iter().map(|e| e.id.0).collect();
+        assert_eq!(ids, vec![2, 1, 3, 4, 0]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut scene = Scene::new();
+        scene.add_entity_from_components(Components {
+            camera_follow: Some(CameraFollow {}),
+            sprite: Some(SpriteComponent {
+                atlas: AtlasId(1),
+                x: 16,
+                y: 0,
+                width: 16,
+                height: 16,
+            }),
+            ..Entity::from_tuples(
+                EntityId(0),
+                (1.0, 2.0),
+                (0.5, 0.0),
+                (1.0, 1.0),
+                Some('@'),
+                true,
+            )
+            .components
+        });
+        let removed = scene.add_entity_from_components(emptys());
+        scene.remove_entity(removed);
+
+        let json = serde_json::to_string(&scene).unwrap();
+        let mut loaded: Scene = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.entities(), scene.entities());
+        assert_eq!(loaded.add_entity_from_components(emptys()), EntityId(2));
+
+        // Missing components are `None`, so hand-written levels only list what they use.
+        let loaded: Scene = serde_json::from_str(
+            r#"{"next_entity_id":1,"entities":[{"id":0,"components":{"position":{"x":3.0,"y":4.0}}}]}"#,
+        )
+        .unwrap();
+        let components = loaded.entities()[0].components;
+        assert_eq!(
+            components.position,
+            Some(PositionComponent { x: 3.0, y: 4.0 })
+        );
+        assert_eq!(components.render, None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialized_ids_follow_saved_entities() {
+        let entities = r#"[{"id":0,"components":{}},{"id":5,"components":{}}]"#;
+        let behind = format!(r#"{{"next_entity_id":2,"entities":{entities}}}"#);
+        let missing = format!(r#"{{"entities":{entities}}}"#);
+        let ahead = format!(r#"{{"next_entity_id":9,"entities":{entities}}}"#);
+        for (json, next) in [(behind, 6), (missing, 6), (ahead, 9)] {
+            let mut scene: Scene = serde_json::from_str(&json).unwrap();
+            assert_eq!(scene.add_entity_from_components(emptys()), EntityId(next));
+        }
+    }
 }
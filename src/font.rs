@@ -0,0 +1,80 @@
+//! Built-in monospace bitmap font.
+//!
+//! The 6x10 glyphs are from the public domain X11 "misc-fixed" font and cover printable
+//! ASCII. They are stored as a 1 bit per pixel atlas of 16 glyphs per row, starting at
+//! `' '`. Characters outside the atlas are drawn as `'?'`.
+
+pub(crate) const GLYPH_WIDTH: u32 = 6;
+pub(crate) const GLYPH_HEIGHT: u32 = 10;
+
+const COLUMNS: u32 = 16;
+const ROWS: u32 = 6;
+const FIRST_CHAR: u32 = ' ' as u32;
+const LAST_CHAR: u32 = '~' as u32;
+
+pub(crate) const ATLAS_WIDTH: u32 = COLUMNS * GLYPH_WIDTH;
+pub(crate) const ATLAS_HEIGHT: u32 = ROWS * GLYPH_HEIGHT;
+
+static ATLAS: &[u8; (ATLAS_WIDTH * ATLAS_HEIGHT / 8) as usize] =
+    include_bytes!("font/font_6x10.raw");
+
+/// Top-left pixel of the glyph for `c` in the atlas.
+pub(crate) fn glyph_origin(c: char) -> (u32, u32) {
+    let code = match c as u32 {
+        code @ FIRST_CHAR..=LAST_CHAR => code,
+        _ => '?' as u32,
+    };
+    let index = code - FIRST_CHAR;
+    (
+        (index % COLUMNS) * GLYPH_WIDTH,
+        (index / COLUMNS) * GLYPH_HEIGHT,
+    )
+}
+
+/// Whether the pixel at `(x, y)` of the atlas is set.
+pub(crate) fn atlas_pixel(x: u32, y: u32) -> bool {
+    let bit = y * ATLAS_WIDTH + x;
+    ATLAS[(bit / 8) as usize] & (0x80 >> (bit % 8)) != 0
+}
+
+/// The atlas as white glyphs on a transparent background, ready to be tinted.
+pub(crate) fn atlas_image() -> image::RgbaImage {
+    image::RgbaImage::from_fn(ATLAS_WIDTH, ATLAS_HEIGHT, |x, y| {
+        image::Rgba([255, 255, 255, if atlas_pixel(x, y) { 255 } else { 0 }])
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rows(c: char) -> Vec<String> {
+        let (origin_x, origin_y) = glyph_origin(c);
+        (0..GLYPH_HEIGHT)
+            .map(|y| {
+                (0..GLYPH_WIDTH)
+                    .map(|x| {
+                        if atlas_pixel(origin_x + x, origin_y + y) {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_glyphs() {
+        assert_eq!(
+            rows('A'),
+            vec![
+                "......", "..#...", ".#.#..", "#...#.", "#...#.", "#####.", "#...#.", "#...#.",
+                "......", "......",
+            ]
+        );
+        assert!(rows(' ').iter().all(|row| row == "......"));
+        assert_eq!(rows('é'), rows('?'));
+    }
+}
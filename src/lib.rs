@@ -10,8 +10,10 @@
 //! For more information, see the [README](https://github.com/joshua-mason/hewn).
 
 mod engine;
+mod font;
 
 pub mod runtime;
+pub mod software;
 pub mod terminal;
 pub mod wgpu;
 
@@ -1,5 +1,23 @@
+pub mod camera;
+pub mod capture;
+pub mod debug_draw;
+pub mod gamepad;
+pub mod hud;
+pub(crate) mod inspector;
+pub mod profiler;
+pub mod recording;
+#[cfg(feature = "serde")]
+pub mod save;
+pub mod state_stack;
+
 use std::time::Duration;
 
+use anyhow::{ensure, Result};
+
+use crate::runtime::camera::CameraConfig;
+use crate::runtime::gamepad::GamepadEvent;
+use crate::runtime::hud::Hud;
+use crate::runtime::profiler::Profiler;
 use crate::scene::Scene;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
@@ -10,13 +28,96 @@ pub trait GameHandler {
     fn start_game(&mut self);
     /// Compute the next game state based on player input.
     fn next(&mut self, dt: Duration);
+
+    /// [`GameHandler::next`] with the runtime's profiler, which the runtimes and
+    /// [`Recording::replay`](recording::Recording::replay) call instead so a game can time
+    /// its own scopes within `update`, e.g.
+    /// `profiler.time("collision", || self.scene.collision_pass(dt))`. By default it just
+    /// calls `next`.
+    fn next_profiled(&mut self, dt: Duration, _profiler: &mut Profiler) {
+        self.next(dt);
+    }
     /// Get the game scene
     fn scene(&self) -> &Scene;
 
-    /// Get a string for debugging.
+    /// The scenes to draw, bottom first, e.g. a paused game beneath its pause menu. Their
+    /// entities are drawn together in layer order. Entity ids only need to be unique within
+    /// a scene: the camera target, the inspector and debug drawing all use
+    /// [`GameHandler::scene`], which should be the last of these.
+    fn scenes(&self) -> Vec<&Scene> {
+        vec![self.scene()]
+    }
+
+    /// Mutable access to the scene, letting the inspector console edit it live. Games that
+    /// don't provide it can still be inspected but not edited.
+    fn scene_mut(&mut self) -> Option<&mut Scene> {
+        None
+    }
+
+    /// Get a string for debugging, shown on the bottom line of the terminal or window.
     fn debug_str(&self) -> Option<String>;
 
     fn handle_key(&mut self, key: Key, pressed: bool) -> bool;
+
+    /// Handle a gamepad event. Gamepad buttons and sticks are also delivered to
+    /// [`GameHandler::handle_key`] through the runtime's key mapping.
+    fn handle_gamepad(&mut self, _event: GamepadEvent) -> bool {
+        false
+    }
+
+    /// Whether the game wants text input, e.g. for name entry. While enabled, characters
+    /// (including space and `q`) are delivered to [`GameHandler::handle_text`] instead of
+    /// being treated as key presses.
+    fn text_input_enabled(&self) -> bool {
+        false
+    }
+
+    /// Handle text input while [`GameHandler::text_input_enabled`] is true.
+    fn handle_text(&mut self, _input: TextInput) {}
+
+    /// How the window camera follows its target. Queried every frame, so the game can
+    /// change it during play, e.g. to shake the screen.
+    fn camera(&self) -> CameraConfig {
+        CameraConfig::default()
+    }
+
+    /// Fill the window's screen-space overlay for this frame, e.g. with the score. Not drawn
+    /// by the terminal runtime.
+    fn hud(&self, _hud: &mut Hud) {}
+
+    /// Called after every frame with the runtime's frame and scope timings, e.g. to show the
+    /// frame rate.
+    fn on_profile(&mut self, _profiler: &Profiler) {}
+
+    /// Whether the game has finished. Checked every frame; once true the runtime calls
+    /// [`GameHandler::on_exit`] and stops, as if the player had quit.
+    fn should_exit(&self) -> bool {
+        false
+    }
+
+    /// Called once when the runtime is about to exit, e.g. to save progress.
+    fn on_exit(&mut self) {}
+
+    /// Called when the window gains or loses focus. Terminals don't report focus, so the
+    /// terminal runtime never calls this.
+    fn on_focus_changed(&mut self, _focused: bool) {}
+
+    /// Called when the window (in pixels) or terminal (in characters) is resized.
+    fn on_resize(&mut self, _width: u32, _height: u32) {}
+
+    /// Scale applied to `dt` before [`GameHandler::next`], on top of the runtime's own time
+    /// scale, e.g. below 1 for bullet-time. Queried every frame. It is clamped to between 0
+    /// and 16, and a value that isn't finite is ignored.
+    fn time_scale(&self) -> f32 {
+        1.0
+    }
+
+    /// Called when the runtime pauses, with F5 in either runtime or when the window loses
+    /// focus. [`GameHandler::next`] is not called while paused.
+    fn on_pause(&mut self) {}
+
+    /// Called when the runtime resumes after a pause.
+    fn on_resume(&mut self) {}
 }
 
 /// Key for player control.
@@ -30,3 +131,305 @@ pub enum Key {
     Escape,
     Q,
 }
+
+/// Text input delivered while a game has text input enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextInput {
+    Char(char),
+    Backspace,
+    Enter,
+}
+
+/// Slowest and fastest time scales reachable with the slow down and speed up keys.
+const TIME_SCALE_RANGE: (f32, f32) = (1.0 / 16.0, 16.0);
+
+/// The game's [`GameHandler::time_scale`], between 0 and the fastest runtime time scale, or 1
+/// if it isn't finite.
+pub(crate) fn game_time_scale(game: &dyn GameHandler) -> f32 {
+    let time_scale = game.time_scale();
+    if time_scale.is_finite() {
+        time_scale.clamp(0.0, TIME_SCALE_RANGE.1)
+    } else {
+        1.0
+    }
+}
+
+/// Runtime state shared by the terminal and window runtimes.
+#[derive(Debug)]
+pub(crate) struct RuntimeState {
+    paused: bool,
+    /// Whether the pause came from the window losing focus, so regaining it resumes.
+    paused_by_focus: bool,
+    /// Frames to run while paused.
+    steps: u32,
+    /// The runtime's time scale, multiplied by the game's [`GameHandler::time_scale`].
+    time_scale: f32,
+    /// `dt` of the latest frame run in real time, reused for stepped frames.
+    last_dt: Duration,
+    /// `dt` last passed to [`GameHandler::next`], for debug drawing.
+    game_dt: Duration,
+}
+
+impl Default for RuntimeState {
+    fn default() -> RuntimeState {
+        RuntimeState {
+            paused: false,
+            paused_by_focus: false,
+            steps: 0,
+            time_scale: 1.0,
+            last_dt: Duration::from_secs_f32(1.0 / 60.0),
+            game_dt: Duration::ZERO,
+        }
+    }
+}
+
+impl RuntimeState {
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Run `frames` more frames while paused.
+    pub(crate) fn step(&mut self, frames: u32) {
+        self.steps = self.steps.saturating_add(frames);
+    }
+
+    pub(crate) fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// The `dt` of the latest frame the game was updated.
+    pub(crate) fn game_dt(&self) -> Duration {
+        self.game_dt
+    }
+
+    /// Set the time scale, clamped to [`TIME_SCALE_RANGE`].
+    pub(crate) fn set_time_scale(&mut self, time_scale: f32) -> Result<()> {
+        ensure!(time_scale.is_finite(), "Invalid time scale {}", time_scale);
+        self.time_scale = time_scale.clamp(TIME_SCALE_RANGE.0, TIME_SCALE_RANGE.1);
+        Ok(())
+    }
+
+    /// Halve or double the time scale, within [`TIME_SCALE_RANGE`].
+    pub(crate) fn slow_down(&mut self) {
+        self.time_scale = (self.time_scale / 2.0).max(TIME_SCALE_RANGE.0);
+    }
+
+    pub(crate) fn speed_up(&mut self) {
+        self.time_scale = (self.time_scale * 2.0).min(TIME_SCALE_RANGE.1);
+    }
+
+    /// The `dt` to pass to [`GameHandler::next`] after `dt` of real time, scaled by both
+    /// time scales, or `None` to skip the update while paused. Each requested step runs one
+    /// frame as long as the latest real one.
+    pub(crate) fn next_dt(&mut self, game: &dyn GameHandler, dt: Duration) -> Option<Duration> {
+        let dt = if !self.paused {
+            self.last_dt = dt;
+            dt
+        } else if self.steps > 0 {
+            self.steps -= 1;
+            self.last_dt
+        } else {
+            return None;
+        };
+        self.game_dt = dt.mul_f32(self.time_scale * game_time_scale(game));
+        Some(self.game_dt)
+    }
+
+    /// Handle the runtime's function keys: F5 pauses or resumes, F6 pauses and steps one
+    /// frame, F7 halves the time scale and F8 doubles it. Returns whether `number` is one
+    /// of them.
+    pub(crate) fn handle_function_key(&mut self, game: &mut dyn GameHandler, number: u8) -> bool {
+        match number {
+            5 => self.set_paused(game, !self.paused),
+            6 => {
+                self.set_paused(game, true);
+                self.step(1);
+            }
+            7 => self.slow_down(),
+            8 => self.speed_up(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// A short description of the pause and time scale, or `None` when running normally.
+    pub(crate) fn status(&self) -> Option<String> {
+        match (self.paused, self.time_scale == 1.0) {
+            (false, true) => None,
+            (false, false) => Some(format!("x{}", self.time_scale)),
+            (true, true) => Some("Paused".to_string()),
+            (true, false) => Some(format!("Paused x{}", self.time_scale)),
+        }
+    }
+
+    /// Pause or resume the game, notifying it if the state changed.
+    pub(crate) fn set_paused(&mut self, game: &mut dyn GameHandler, paused: bool) {
+        if self.paused == paused {
+            return;
+        }
+        self.paused = paused;
+        self.paused_by_focus = false;
+        if paused {
+            game.on_pause();
+        } else {
+            game.on_resume();
+        }
+    }
+
+    /// Pause while the window is unfocused. Regaining focus only resumes if losing it
+    /// paused the game, so a game paused with F5 stays paused.
+    pub(crate) fn set_focused(&mut self, game: &mut dyn GameHandler, focused: bool) {
+        if !focused && !self.paused {
+            self.set_paused(game, true);
+            self.paused_by_focus = true;
+        } else if focused && self.paused_by_focus {
+            self.set_paused(game, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct PausingGame {
+        scene: Scene,
+        pauses: u32,
+        resumes: u32,
+        time_scale: f32,
+    }
+
+    impl Default for PausingGame {
+        fn default() -> PausingGame {
+            PausingGame {
+                scene: Scene::new(),
+                pauses: 0,
+                resumes: 0,
+                time_scale: 1.0,
+            }
+        }
+    }
+
+    impl GameHandler for PausingGame {
+        fn start_game(&mut self) {}
+        fn next(&mut self, _dt: Duration) {}
+        fn scene(&self) -> &Scene {
+            &self.scene
+        }
+        fn debug_str(&self) -> Option<String> {
+            None
+        }
+        fn handle_key(&mut self, _key: Key, _pressed: bool) -> bool {
+            false
+        }
+        fn on_pause(&mut self) {
+            self.pauses += 1;
+        }
+        fn on_resume(&mut self) {
+            self.resumes += 1;
+        }
+        fn time_scale(&self) -> f32 {
+            self.time_scale
+        }
+    }
+
+    #[test]
+    fn test_runtime_state_only_notifies_on_change() {
+        let mut game = PausingGame::default();
+        let mut state = RuntimeState::default();
+
+        state.set_paused(&mut game, false);
+        assert_eq!((game.pauses, game.resumes), (0, 0));
+
+        state.set_paused(&mut game, true);
+        state.set_paused(&mut game, true);
+        assert!(state.is_paused());
+        assert_eq!((game.pauses, game.resumes), (1, 0));
+
+        state.set_paused(&mut game, false);
+        assert!(!state.is_paused());
+        assert_eq!((game.pauses, game.resumes), (1, 1));
+    }
+
+    #[test]
+    fn test_steps_run_single_frames_while_paused() {
+        let mut game = PausingGame::default();
+        let mut state = RuntimeState::default();
+        let frame = Duration::from_millis(20);
+        assert_eq!(state.next_dt(&game, frame), Some(frame));
+
+        state.set_paused(&mut game, true);
+        state.step(2);
+        let long_pause = Duration::from_secs(5);
+        assert_eq!(state.next_dt(&game, long_pause), Some(frame));
+        assert_eq!(state.next_dt(&game, frame), Some(frame));
+        assert_eq!(state.next_dt(&game, frame), None);
+        assert_eq!(state.status().unwrap(), "Paused");
+
+        state.step(u32::MAX);
+        state.step(1);
+        assert_eq!(state.next_dt(&game, frame), Some(frame));
+    }
+
+    #[test]
+    fn test_focus_only_resumes_its_own_pause() {
+        let mut game = PausingGame::default();
+        let mut state = RuntimeState::default();
+        state.set_focused(&mut game, false);
+        assert!(state.is_paused());
+        state.set_focused(&mut game, true);
+        assert!(!state.is_paused());
+
+        state.set_paused(&mut game, true);
+        state.set_focused(&mut game, false);
+        state.set_focused(&mut game, true);
+        assert!(state.is_paused());
+    }
+
+    #[test]
+    fn test_time_scales_multiply() {
+        let mut game = PausingGame {
+            time_scale: 0.5,
+            ..PausingGame::default()
+        };
+        let mut state = RuntimeState::default();
+        state.speed_up();
+        state.speed_up();
+        assert_eq!(
+            state.next_dt(&game, Duration::from_millis(10)),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(state.status().unwrap(), "x4");
+
+        (0..10).for_each(|_| state.slow_down());
+        assert_eq!(state.time_scale(), 1.0 / 16.0);
+        game.time_scale = -1.0;
+        assert_eq!(
+            state.next_dt(&game, Duration::from_millis(10)),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_time_scales_are_clamped_and_finite() {
+        let mut game = PausingGame::default();
+        let mut state = RuntimeState::default();
+        assert!(state.set_time_scale(f32::INFINITY).is_err());
+        assert!(state.set_time_scale(f32::NAN).is_err());
+        assert_eq!(state.time_scale(), 1.0);
+
+        state.set_time_scale(1e30).unwrap();
+        assert_eq!(state.time_scale(), TIME_SCALE_RANGE.1);
+        state.set_time_scale(0.0).unwrap();
+        assert_eq!(state.time_scale(), TIME_SCALE_RANGE.0);
+
+        state.set_time_scale(1.0).unwrap();
+        let frame = Duration::from_millis(500);
+        game.time_scale = f32::INFINITY;
+        assert_eq!(state.next_dt(&game, frame), Some(frame));
+        game.time_scale = f32::NAN;
+        assert_eq!(state.next_dt(&game, frame), Some(frame));
+        game.time_scale = 1e30;
+        assert_eq!(state.next_dt(&game, frame), Some(Duration::from_secs(8)));
+    }
+}
@@ -0,0 +1,358 @@
+//! Camera behaviour shared by the runtimes.
+//!
+//! A game returns a [`CameraConfig`] from [`GameHandler::camera`](crate::runtime::GameHandler::camera)
+//! each frame, so the target, smoothing, dead zones, bounds, look-ahead and shake can change
+//! during play. [`CameraTargeting`] picks the entity to follow and [`CameraController`] turns
+//! that into where the camera actually looks.
+
+use crate::scene::{Entity, EntityId, SizeComponent};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// What the camera looks at.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub enum CameraStrategy {
+    /// Fit every entity in view. The terminal can't fit the view to the scene, so it follows
+    /// the first entity with a `CameraFollow` component instead.
+    #[default]
+    AllEntities,
+    /// Follow an entity, falling back as described on [`CameraTargeting`] if it is missing.
+    CameraFollow(EntityId),
+}
+
+/// A rectangle in world units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+/// How the camera follows its target. The default snaps to the target every frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CameraConfig {
+    /// Override the camera strategy the runtime was started with.
+    pub target: Option<CameraStrategy>,
+    /// Approach the target exponentially at this rate per second instead of snapping to it.
+    /// Higher is snappier.
+    pub smoothing: Option<f32>,
+    /// Half the width and height of a rectangle around the camera's centre in which the
+    /// target can move without the camera moving.
+    pub dead_zone: (f32, f32),
+    /// Keep the view inside these bounds. If the view is larger than the bounds it is centred
+    /// on them.
+    pub bounds: Option<Bounds>,
+    /// Look ahead of a moving target by this many seconds of its velocity.
+    pub look_ahead: f32,
+    /// Offset the camera randomly by up to this distance each frame. Set it for a moment (or
+    /// decay it over a few frames) to shake the screen. The offsets come from a fixed seed,
+    /// so a replayed run shakes the same way.
+    pub shake: f32,
+}
+
+/// The point the camera follows and how fast it is moving, in world units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraTarget {
+    pub position: (f32, f32),
+    pub velocity: (f32, f32),
+}
+
+/// Picks the entity the camera follows, with the same rules in every runtime:
+///
+/// 1. the entity being followed, if it is still in the scene;
+/// 2. otherwise the first entity with a `CameraFollow` component;
+/// 3. otherwise wherever the camera was last following.
+///
+/// The camera looks at the centre of the entity.
+#[derive(Debug, Default)]
+pub struct CameraTargeting {
+    last: Option<(f32, f32)>,
+}
+
+impl CameraTargeting {
+    pub fn new() -> CameraTargeting {
+        CameraTargeting::default()
+    }
+
+    /// The camera target, or `None` if nothing has been followed yet.
+    pub fn target<'a>(
+        &mut self,
+        follow: Option<EntityId>,
+        entities: impl IntoIterator<Item = &'a Entity> + Clone,
+    ) -> Option<CameraTarget> {
+        let followed = follow
+            .and_then(|id| entities.clone().into_iter().find(|e| e.id == id))
+            .filter(|e| e.components.position.is_some())
+            .or_else(|| {
+                entities.into_iter().find(|e| {
+                    e.components.camera_follow.is_some() && e.components.position.is_some()
+                })
+            });
+
+        let Some(entity) = followed else {
+            return self.last.map(|position| CameraTarget {
+                position,
+                velocity: (0.0, 0.0),
+            });
+        };
+        let position = entity.components.position.unwrap();
+        let size = entity
+            .components
+            .size
+            .unwrap_or(SizeComponent { x: 1.0, y: 1.0 });
+        let centre = (position.x + size.x / 2.0, position.y + size.y / 2.0);
+        self.last = Some(centre);
+        Some(CameraTarget {
+            position: centre,
+            velocity: entity
+                .components
+                .velocity
+                .map_or((0.0, 0.0), |velocity| (velocity.x, velocity.y)),
+        })
+    }
+}
+
+/// Seed of every controller's shake, so replays and captures shake the same way each run.
+const SHAKE_SEED: u64 = 0x5eed;
+
+/// The camera's position, smoothed and constrained by a [`CameraConfig`].
+#[derive(Debug)]
+pub struct CameraController {
+    position: Option<(f32, f32)>,
+    shake_rng: StdRng,
+}
+
+impl Default for CameraController {
+    fn default() -> CameraController {
+        CameraController {
+            position: None,
+            shake_rng: StdRng::seed_from_u64(SHAKE_SEED),
+        }
+    }
+}
+
+impl CameraController {
+    pub fn new() -> CameraController {
+        CameraController::default()
+    }
+
+    /// Move towards `target` (moving at `velocity`) and return the centre of the view.
+    /// `half_view` is half the visible width and height in world units, used for bounds.
+    pub fn update(
+        &mut self,
+        config: &CameraConfig,
+        target: (f32, f32),
+        velocity: (f32, f32),
+        half_view: (f32, f32),
+        dt: f32,
+    ) -> (f32, f32) {
+        let goal = (
+            target.0 + velocity.0 * config.look_ahead,
+            target.1 + velocity.1 * config.look_ahead,
+        );
+        let Some(position) = self.position else {
+            let position = clamp_to_bounds(goal, half_view, config.bounds);
+            self.position = Some(position);
+            return self.with_shake(config, position);
+        };
+
+        let goal = (
+            follow_dead_zone(position.0, goal.0, config.dead_zone.0),
+            follow_dead_zone(position.1, goal.1, config.dead_zone.1),
+        );
+        let blend = match config.smoothing {
+            Some(rate) => 1.0 - (-rate * dt).exp(),
+            None => 1.0,
+        };
+        let position = (
+            position.0 + (goal.0 - position.0) * blend,
+            position.1 + (goal.1 - position.1) * blend,
+        );
+        let position = clamp_to_bounds(position, half_view, config.bounds);
+        self.position = Some(position);
+        self.with_shake(config, position)
+    }
+
+    fn with_shake(&mut self, config: &CameraConfig, position: (f32, f32)) -> (f32, f32) {
+        if config.shake <= 0.0 {
+            return position;
+        }
+        let mut offset = || self.shake_rng.gen_range(-1.0..=1.0) * config.shake;
+        (position.0 + offset(), position.1 + offset())
+    }
+}
+
+/// Where the camera should be on one axis so `goal` is within `half_size` of it.
+fn follow_dead_zone(position: f32, goal: f32, half_size: f32) -> f32 {
+    if goal > position + half_size {
+        goal - half_size
+    } else if goal < position - half_size {
+        goal + half_size
+    } else {
+        position
+    }
+}
+
+fn clamp_to_bounds(
+    position: (f32, f32),
+    half_view: (f32, f32),
+    bounds: Option<Bounds>,
+) -> (f32, f32) {
+    let Some(bounds) = bounds else {
+        return position;
+    };
+    let clamp_axis = |value: f32, half: f32, min: f32, max: f32| {
+        if max - min <= half * 2.0 {
+            (min + max) / 2.0
+        } else {
+            value.clamp(min + half, max - half)
+        }
+    };
+    (
+        clamp_axis(position.0, half_view.0, bounds.min_x, bounds.max_x),
+        clamp_axis(position.1, half_view.1, bounds.min_y, bounds.max_y),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scene::CameraFollow;
+
+    const VIEW: (f32, f32) = (5.0, 5.0);
+
+    #[test]
+    fn test_default_snaps_to_target() {
+        let mut controller = CameraController::new();
+        let config = CameraConfig::default();
+        assert_eq!(
+            controller.update(&config, (1.0, 2.0), (0.0, 0.0), VIEW, 0.1),
+            (1.0, 2.0)
+        );
+        assert_eq!(
+            controller.update(&config, (7.0, -3.0), (0.0, 0.0), VIEW, 0.1),
+            (7.0, -3.0)
+        );
+    }
+
+    #[test]
+    fn test_dead_zone_and_look_ahead() {
+        let mut controller = CameraController::new();
+        let config = CameraConfig {
+            dead_zone: (2.0, 1.0),
+            ..CameraConfig::default()
+        };
+        controller.update(&config, (0.0, 0.0), (0.0, 0.0), VIEW, 0.1);
+        assert_eq!(
+            controller.update(&config, (1.5, 0.5), (0.0, 0.0), VIEW, 0.1),
+            (0.0, 0.0)
+        );
+        assert_eq!(
+            controller.update(&config, (3.0, 0.0), (0.0, 0.0), VIEW, 0.1),
+            (1.0, 0.0)
+        );
+
+        let config = CameraConfig {
+            look_ahead: 0.5,
+            ..CameraConfig::default()
+        };
+        assert_eq!(
+            controller.update(&config, (3.0, 0.0), (4.0, -2.0), VIEW, 0.1),
+            (5.0, -1.0)
+        );
+    }
+
+    #[test]
+    fn test_smoothing_approaches_target() {
+        let mut controller = CameraController::new();
+        let config = CameraConfig {
+            smoothing: Some(5.0),
+            ..CameraConfig::default()
+        };
+        controller.update(&config, (0.0, 0.0), (0.0, 0.0), VIEW, 0.1);
+        let (x, _) = controller.update(&config, (10.0, 0.0), (0.0, 0.0), VIEW, 0.1);
+        assert!(x > 0.0 && x < 10.0);
+        let (next_x, _) = controller.update(&config, (10.0, 0.0), (0.0, 0.0), VIEW, 0.1);
+        assert!(next_x > x && next_x < 10.0);
+    }
+
+    #[test]
+    fn test_bounds_keep_view_inside() {
+        let mut controller = CameraController::new();
+        let config = CameraConfig {
+            bounds: Some(Bounds {
+                min_x: 0.0,
+                min_y: 0.0,
+                max_x: 20.0,
+                max_y: 8.0,
+            }),
+            ..CameraConfig::default()
+        };
+        assert_eq!(
+            controller.update(&config, (1.0, 1.0), (0.0, 0.0), VIEW, 0.1),
+            (5.0, 4.0)
+        );
+        assert_eq!(
+            controller.update(&config, (30.0, 4.0), (0.0, 0.0), VIEW, 0.1),
+            (15.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn test_shake_is_bounded_and_repeatable() {
+        let config = CameraConfig {
+            shake: 0.5,
+            ..CameraConfig::default()
+        };
+        let run = || {
+            let mut controller = CameraController::new();
+            (0..10)
+                .map(|_| controller.update(&config, (1.0, 1.0), (0.0, 0.0), VIEW, 0.1))
+                .collect::<Vec<_>>()
+        };
+        let positions = run();
+        assert!(positions
+            .iter()
+            .all(|&(x, y)| (x - 1.0).abs() <= 0.5 && (y - 1.0).abs() <= 0.5));
+        assert!(positions.iter().any(|&position| position != (1.0, 1.0)));
+        assert_eq!(positions, run());
+    }
+
+    #[test]
+    fn test_targeting_falls_back_when_followed_entity_is_missing() {
+        let player = Entity::from_tuples(
+            EntityId(0),
+            (2.0, 2.0),
+            (1.0, 0.0),
+            (1.0, 1.0),
+            Some('@'),
+            false,
+        );
+        let mut tracked =
+            Entity::from_tuples(EntityId(1), (8.0, 0.0), (0.0, 0.0), (2.0, 2.0), None, false);
+        tracked.components.camera_follow = Some(CameraFollow {});
+        let mut targeting = CameraTargeting::new();
+
+        assert_eq!(targeting.target(Some(EntityId(5)), &[]), None);
+        assert_eq!(
+            targeting.target(Some(EntityId(0)), &[player, tracked]),
+            Some(CameraTarget {
+                position: (2.5, 2.5),
+                velocity: (1.0, 0.0),
+            })
+        );
+        assert_eq!(
+            targeting
+                .target(Some(EntityId(0)), &[tracked])
+                .map(|t| t.position),
+            Some((9.0, 1.0))
+        );
+        assert_eq!(
+            targeting.target(Some(EntityId(0)), &[]),
+            Some(CameraTarget {
+                position: (9.0, 1.0),
+                velocity: (0.0, 0.0),
+            })
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Screenshots and frame recordings.
+//!
+//! The window and terminal runtimes save a PNG screenshot when F12 is pressed, once given a
+//! directory with `set_screenshot_dir`, and every runtime can record each frame with a
+//! [`FrameRecorder`]. The window runtime captures the rendered frame; the terminal runtime
+//! draws its text with the built-in bitmap font, so captures work without a GPU.
+
+use crate::font;
+use anyhow::{Context, Result};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Rgba, RgbaImage};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Background of rasterised terminal frames.
+const TERMINAL_BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 255]);
+/// Colour of rasterised terminal text.
+const TERMINAL_FOREGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// Save `frame` as a PNG.
+pub fn save_png(frame: &RgbaImage, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    frame
+        .save_with_format(path, image::ImageFormat::Png)
+        .with_context(|| format!("Unable to save {}", path.display()))
+}
+
+/// Draw lines of text in the built-in font, light on dark like a terminal. Each character
+/// is one 6x10 pixel cell.
+pub fn rasterize_text(lines: &[String]) -> RgbaImage {
+    let columns = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(1) as u32;
+    let rows = lines.len().max(1) as u32;
+    let mut image = RgbaImage::from_pixel(
+        columns * font::GLYPH_WIDTH,
+        rows * font::GLYPH_HEIGHT,
+        TERMINAL_BACKGROUND,
+    );
+    for (row, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let (origin_x, origin_y) = font::glyph_origin(c);
+            for y in 0..font::GLYPH_HEIGHT {
+                for x in 0..font::GLYPH_WIDTH {
+                    if font::atlas_pixel(origin_x + x, origin_y + y) {
+                        image.put_pixel(
+                            column as u32 * font::GLYPH_WIDTH + x,
+                            row as u32 * font::GLYPH_HEIGHT + y,
+                            TERMINAL_FOREGROUND,
+                        );
+                    }
+                }
+            }
+        }
+    }
+    image
+}
+
+/// Where a [`FrameRecorder`] writes its frames.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameOutput {
+    /// A looping animated GIF.
+    Gif(PathBuf),
+    /// Numbered PNGs, `frame_00000.png` onwards, in a directory.
+    Pngs(PathBuf),
+}
+
+/// Writes each frame, and the time it was shown for, as it is pushed, so long recordings
+/// don't build up in memory. [`FrameRecorder::finish`] completes the file.
+pub struct FrameRecorder {
+    output: FrameOutput,
+    /// Created with the first frame of a GIF recording.
+    gif: Option<GifEncoder<fs::File>>,
+    frames: usize,
+}
+
+impl FrameRecorder {
+    pub fn new(output: FrameOutput) -> FrameRecorder {
+        FrameRecorder {
+            output,
+            gif: None,
+            frames: 0,
+        }
+    }
+
+    /// Record a looping animated GIF at `path`.
+    pub fn gif(path: impl Into<PathBuf>) -> FrameRecorder {
+        FrameRecorder::new(FrameOutput::Gif(path.into()))
+    }
+
+    /// Record numbered PNGs into the directory `dir`.
+    pub fn pngs(dir: impl Into<PathBuf>) -> FrameRecorder {
+        FrameRecorder::new(FrameOutput::Pngs(dir.into()))
+    }
+
+    /// Write a frame shown for `duration`.
+    pub fn push(&mut self, frame: RgbaImage, duration: Duration) -> Result<()> {
+        match &self.output {
+            FrameOutput::Gif(path) => {
+                let encoder = match &mut self.gif {
+                    Some(encoder) => encoder,
+                    None => {
+                        let file = fs::File::create(path)
+                            .with_context(|| format!("Unable to create {}", path.display()))?;
+                        let mut encoder = GifEncoder::new(file);
+                        encoder.set_repeat(Repeat::Infinite)?;
+                        self.gif.insert(encoder)
+                    }
+                };
+                encoder.encode_frame(Frame::from_parts(
+                    frame,
+                    0,
+                    0,
+                    Delay::from_saturating_duration(duration),
+                ))?;
+            }
+            FrameOutput::Pngs(dir) => {
+                if self.frames == 0 {
+                    fs::create_dir_all(dir)
+                        .with_context(|| format!("Unable to create {}", dir.display()))?;
+                }
+                save_png(&frame, dir.join(format!("frame_{:05}.png", self.frames)))?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// The number of frames written.
+    pub fn len(&self) -> usize {
+        self.frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames == 0
+    }
+
+    /// Finish the recording, ending the GIF.
+    pub fn finish(mut self) -> Result<()> {
+        // The GIF's trailer is written when its encoder is dropped.
+        drop(self.gif.take());
+        Ok(())
+    }
+}
+
+/// Screenshot and recording state shared by the runtimes.
+#[derive(Default)]
+pub(crate) struct Capture {
+    screenshot_dir: Option<PathBuf>,
+    screenshot_requested: bool,
+    pub(crate) recorder: Option<FrameRecorder>,
+}
+
+impl Capture {
+    pub(crate) fn set_screenshot_dir(&mut self, dir: PathBuf) {
+        self.screenshot_dir = Some(dir);
+    }
+
+    /// Save the next frame as a screenshot, if a screenshot directory is set.
+    pub(crate) fn request_screenshot(&mut self) {
+        self.screenshot_requested = self.screenshot_dir.is_some();
+    }
+
+    /// Whether the runtime should capture the frame it is about to draw.
+    pub(crate) fn wants_frame(&self) -> bool {
+        self.screenshot_requested || self.recorder.is_some()
+    }
+
+    /// Save a requested screenshot and record the frame, shown for `duration`.
+    pub(crate) fn add_frame(&mut self, frame: RgbaImage, duration: Duration) -> Result<()> {
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            if let Some(dir) = &self.screenshot_dir {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Unable to create {}", dir.display()))?;
+                save_png(&frame, next_screenshot_path(dir))?;
+            }
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.push(frame, duration)?;
+        }
+        Ok(())
+    }
+
+    /// Finish the recording, if there is one.
+    pub(crate) fn finish(&mut self) -> Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The first `screenshot_0000.png`, `screenshot_0001.png`, ... in `dir` that doesn't exist.
+fn next_screenshot_path(dir: &Path) -> PathBuf {
+    (0..)
+        .map(|i| dir.join(format!("screenshot_{i:04}.png")))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hewn-capture-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_rasterize_text() {
+        let image = rasterize_text(&["A.".to_string(), "".to_string()]);
+        assert_eq!(image.dimensions(), (12, 20));
+        // The top of the 'A' and nothing on the empty line.
+        assert_eq!(image.get_pixel(2, 1), &TERMINAL_FOREGROUND);
+        assert_eq!(image.get_pixel(1, 1), &TERMINAL_BACKGROUND);
+        assert!((10..20).all(|y| (0..12).all(|x| image.get_pixel(x, y) == &TERMINAL_BACKGROUND)));
+    }
+
+    #[test]
+    fn test_screenshots_and_recordings_are_saved() {
+        let dir = temp_dir("screenshots");
+        let mut capture = Capture::default();
+        capture.request_screenshot();
+        assert!(!capture.wants_frame());
+
+        capture.set_screenshot_dir(dir.clone());
+        fs::create_dir_all(&dir).unwrap();
+        capture.recorder = Some(FrameRecorder::gif(dir.join("run.gif")));
+        let frame = rasterize_text(&["hi".to_string()]);
+        capture
+            .add_frame(frame.clone(), Duration::from_millis(50))
+            .unwrap();
+        capture.request_screenshot();
+        capture
+            .add_frame(frame.clone(), Duration::from_millis(50))
+            .unwrap();
+        capture.request_screenshot();
+        capture.add_frame(frame, Duration::from_millis(50)).unwrap();
+        capture.finish().unwrap();
+
+        assert!(dir.join("screenshot_0000.png").exists());
+        assert!(dir.join("screenshot_0001.png").exists());
+        assert!(!dir.join("screenshot_0002.png").exists());
+        let gif = image::open(dir.join("run.gif")).unwrap();
+        assert_eq!(gif.width(), 12);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_recording_numbered_pngs() {
+        let dir = temp_dir("pngs");
+        let mut recorder = FrameRecorder::pngs(&dir);
+        recorder.push(rasterize_text(&[]), Duration::ZERO).unwrap();
+        assert!(dir.join("frame_00000.png").exists());
+        recorder.push(rasterize_text(&[]), Duration::ZERO).unwrap();
+        assert_eq!(recorder.len(), 2);
+        recorder.finish().unwrap();
+
+        assert!(dir.join("frame_00001.png").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
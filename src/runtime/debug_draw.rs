@@ -0,0 +1,87 @@
+//! Collision debug overlay.
+//!
+//! When enabled (F3 in either runtime, or `set_debug_draw`), the runtimes draw each
+//! entity's swept collision box, velocity and id over the scene, highlighting entities in
+//! the pairs the game's latest [`Scene::collision_pass`] returned.
+
+use crate::scene::collisions::CollisionBox;
+use crate::scene::{EntityId, Scene};
+use std::ops::Range;
+use std::time::Duration;
+
+/// What the overlay shows for one entity, in world units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugBox {
+    pub id: EntityId,
+    /// The swept collision box, covering the entity and how far it moves over `dt`.
+    pub x: Range<f32>,
+    pub y: Range<f32>,
+    pub centre: (f32, f32),
+    pub velocity: (f32, f32),
+    /// Whether the entity is in a pair from the scene's latest collision pass.
+    pub colliding: bool,
+}
+
+/// Debug boxes for every entity in `scene` with a position and size, swept over `dt`. The
+/// runtimes pass the `dt` of the game's latest frame.
+pub fn debug_boxes(scene: &Scene, dt: Duration) -> Vec<DebugBox> {
+    let collisions = scene.last_collisions();
+    scene
+        .entities()
+        .iter()
+        .filter_map(|entity| {
+            let collision_box = CollisionBox::from_entity(entity, dt)?;
+            let position = entity.components.position?;
+            let size = entity.components.size?;
+            Some(DebugBox {
+                id: entity.id,
+                x: collision_box.x,
+                y: collision_box.y,
+                centre: (position.x + size.x / 2.0, position.y + size.y / 2.0),
+                velocity: entity
+                    .components
+                    .velocity
+                    .map_or((0.0, 0.0), |velocity| (velocity.x, velocity.y)),
+                colliding: collisions.iter().flatten().any(|id| *id == entity.id),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scene::{Components, PositionComponent, SizeComponent, VelocityComponent};
+
+    fn add(scene: &mut Scene, x: f32, velocity_x: f32) -> EntityId {
+        let mut components = Components::new();
+        components.position = Some(PositionComponent { x, y: 0.0 });
+        components.velocity = Some(VelocityComponent {
+            x: velocity_x,
+            y: 0.0,
+        });
+        components.size = Some(SizeComponent { x: 1.0, y: 1.0 });
+        scene.add_entity_from_components(components)
+    }
+
+    #[test]
+    fn test_boxes_are_swept_and_collisions_highlighted() {
+        let mut scene = Scene::new();
+        let mover = add(&mut scene, 0.0, 4.0);
+        let target = add(&mut scene, 2.5, 0.0);
+        let bystander = add(&mut scene, 10.0, 0.0);
+        let dt = Duration::from_millis(500);
+        assert!(debug_boxes(&scene, Duration::ZERO)
+            .iter()
+            .all(|b| !b.colliding));
+        assert_eq!(scene.collision_pass(dt), vec![[mover, target]]);
+
+        let boxes = debug_boxes(&scene, dt);
+        assert_eq!(boxes[0].x, 0.0..3.0);
+        assert_eq!(boxes[0].velocity, (4.0, 0.0));
+        assert_eq!(boxes[0].centre, (0.5, 0.5));
+        let colliding: Vec<EntityId> = boxes.iter().filter(|b| b.colliding).map(|b| b.id).collect();
+        assert_eq!(colliding, vec![mover, target]);
+        assert!(!boxes.iter().any(|b| b.id == bystander && b.colliding));
+    }
+}
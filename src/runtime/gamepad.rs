@@ -0,0 +1,469 @@
+//! Gamepad events, state and backends.
+//!
+//! Runtimes poll a [`GamepadBackend`] once per frame. Each event is delivered to
+//! [`GameHandler::handle_gamepad`](crate::runtime::GameHandler::handle_gamepad) and then
+//! translated through a [`GamepadKeyMap`] into ordinary [`Key`] presses, so games written
+//! against the keyboard work with a controller without a separate code path.
+
+use crate::runtime::recording::InputEvent;
+use crate::runtime::Key;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct GamepadId(pub u8);
+
+/// Gamepad buttons, named by position so they are layout agnostic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    LeftShoulder,
+    RightShoulder,
+    LeftStick,
+    RightStick,
+    Select,
+    Start,
+}
+
+/// Analogue inputs. Sticks range from -1.0 to 1.0 (positive is right/up), triggers from
+/// 0.0 to 1.0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GamepadEvent {
+    Connected(GamepadId),
+    Disconnected(GamepadId),
+    Button {
+        id: GamepadId,
+        button: GamepadButton,
+        pressed: bool,
+    },
+    Axis {
+        id: GamepadId,
+        axis: GamepadAxis,
+        value: f32,
+    },
+}
+
+impl GamepadEvent {
+    pub fn id(&self) -> GamepadId {
+        match *self {
+            GamepadEvent::Connected(id) | GamepadEvent::Disconnected(id) => id,
+            GamepadEvent::Button { id, .. } | GamepadEvent::Axis { id, .. } => id,
+        }
+    }
+}
+
+/// The current state of a single gamepad.
+#[derive(Debug, Clone, Default)]
+pub struct GamepadState {
+    pressed: HashSet<GamepadButton>,
+    axes: HashMap<GamepadAxis, f32>,
+}
+
+impl GamepadState {
+    pub fn is_pressed(&self, button: GamepadButton) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes.get(&axis).copied().unwrap_or(0.0)
+    }
+}
+
+/// Tracks the state of every connected gamepad from a stream of events.
+#[derive(Debug, Clone, Default)]
+pub struct Gamepads {
+    pads: HashMap<GamepadId, GamepadState>,
+}
+
+impl Gamepads {
+    pub fn new() -> Gamepads {
+        Gamepads::default()
+    }
+
+    pub fn apply(&mut self, event: &GamepadEvent) {
+        match *event {
+            GamepadEvent::Connected(id) => {
+                self.pads.insert(id, GamepadState::default());
+            }
+            GamepadEvent::Disconnected(id) => {
+                self.pads.remove(&id);
+            }
+            GamepadEvent::Button {
+                id,
+                button,
+                pressed,
+            } => {
+                let pad = self.pads.entry(id).or_default();
+                if pressed {
+                    pad.pressed.insert(button);
+                } else {
+                    pad.pressed.remove(&button);
+                }
+            }
+            GamepadEvent::Axis { id, axis, value } => {
+                self.pads.entry(id).or_default().axes.insert(axis, value);
+            }
+        }
+    }
+
+    pub fn get(&self, id: GamepadId) -> Option<&GamepadState> {
+        self.pads.get(&id)
+    }
+
+    pub fn connected(&self) -> impl Iterator<Item = GamepadId> + '_ {
+        self.pads.keys().copied()
+    }
+}
+
+/// A source of gamepad events, polled by the runtime once per frame.
+pub trait GamepadBackend {
+    fn poll(&mut self) -> Vec<GamepadEvent>;
+}
+
+/// A scripted gamepad for tests and demos. Each call to [`GamepadBackend::poll`] returns
+/// the events queued for the next frame.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualGamepad {
+    id: GamepadId,
+    frames: VecDeque<Vec<GamepadEvent>>,
+}
+
+impl VirtualGamepad {
+    pub fn new(id: GamepadId) -> VirtualGamepad {
+        VirtualGamepad {
+            id,
+            frames: VecDeque::new(),
+        }
+    }
+
+    /// Queue the events for one frame.
+    pub fn frame(mut self, events: Vec<GamepadEvent>) -> VirtualGamepad {
+        self.frames.push_back(events);
+        self
+    }
+
+    /// Queue `count` frames without any events.
+    pub fn idle(mut self, count: usize) -> VirtualGamepad {
+        self.frames.extend((0..count).map(|_| vec![]));
+        self
+    }
+
+    pub fn connect(self) -> VirtualGamepad {
+        let id = self.id;
+        self.frame(vec![GamepadEvent::Connected(id)])
+    }
+
+    pub fn disconnect(self) -> VirtualGamepad {
+        let id = self.id;
+        self.frame(vec![GamepadEvent::Disconnected(id)])
+    }
+
+    pub fn press(self, button: GamepadButton) -> VirtualGamepad {
+        self.button(button, true)
+    }
+
+    pub fn release(self, button: GamepadButton) -> VirtualGamepad {
+        self.button(button, false)
+    }
+
+    pub fn axis(self, axis: GamepadAxis, value: f32) -> VirtualGamepad {
+        let id = self.id;
+        self.frame(vec![GamepadEvent::Axis { id, axis, value }])
+    }
+
+    fn button(self, button: GamepadButton, pressed: bool) -> VirtualGamepad {
+        let id = self.id;
+        self.frame(vec![GamepadEvent::Button {
+            id,
+            button,
+            pressed,
+        }])
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl GamepadBackend for VirtualGamepad {
+    fn poll(&mut self) -> Vec<GamepadEvent> {
+        self.frames.pop_front().unwrap_or_default()
+    }
+}
+
+/// Translates gamepad buttons and stick directions into [`Key`] presses.
+#[derive(Debug, Clone)]
+pub struct GamepadKeyMap {
+    pub buttons: HashMap<GamepadButton, Key>,
+    /// Stick deflection past which the left stick counts as an arrow key press.
+    pub stick_threshold: Option<f32>,
+    stick_keys: HashSet<(GamepadId, Key)>,
+    /// The key each held button pressed, released if the pad disconnects.
+    button_keys: HashMap<(GamepadId, GamepadButton), Key>,
+}
+
+impl Default for GamepadKeyMap {
+    fn default() -> Self {
+        let buttons = HashMap::from([
+            (GamepadButton::DPadUp, Key::Up),
+            (GamepadButton::DPadDown, Key::Down),
+            (GamepadButton::DPadLeft, Key::Left),
+            (GamepadButton::DPadRight, Key::Right),
+            (GamepadButton::South, Key::Space),
+            (GamepadButton::Start, Key::Space),
+        ]);
+        GamepadKeyMap {
+            buttons,
+            stick_threshold: Some(0.5),
+            stick_keys: HashSet::new(),
+            button_keys: HashMap::new(),
+        }
+    }
+}
+
+impl GamepadKeyMap {
+    /// A mapping which produces no key presses, for games handling gamepads directly.
+    pub fn empty() -> GamepadKeyMap {
+        GamepadKeyMap {
+            buttons: HashMap::new(),
+            stick_threshold: None,
+            stick_keys: HashSet::new(),
+            button_keys: HashMap::new(),
+        }
+    }
+
+    /// Returns the key presses and releases produced by an event.
+    pub fn map(&mut self, event: &GamepadEvent) -> Vec<(Key, bool)> {
+        match *event {
+            GamepadEvent::Button {
+                id,
+                button,
+                pressed,
+            } => {
+                let key = if pressed {
+                    let key = self.buttons.get(&button).copied();
+                    if let Some(key) = key {
+                        self.button_keys.insert((id, button), key);
+                    }
+                    key
+                } else {
+                    self.button_keys
+                        .remove(&(id, button))
+                        .or_else(|| self.buttons.get(&button).copied())
+                };
+                key.map(|key| vec![(key, pressed)]).unwrap_or_default()
+            }
+            GamepadEvent::Axis { id, axis, value } => {
+                let Some(threshold) = self.stick_threshold else {
+                    return vec![];
+                };
+                let (negative, positive) = match axis {
+                    GamepadAxis::LeftStickX => (Key::Left, Key::Right),
+                    GamepadAxis::LeftStickY => (Key::Down, Key::Up),
+                    _ => return vec![],
+                };
+                let mut keys = vec![];
+                for (key, held) in [
+                    (negative, value <= -threshold),
+                    (positive, value >= threshold),
+                ] {
+                    let was_held = self.stick_keys.contains(&(id, key));
+                    if held && !was_held {
+                        self.stick_keys.insert((id, key));
+                        keys.push((key, true));
+                    } else if !held && was_held {
+                        self.stick_keys.remove(&(id, key));
+                        keys.push((key, false));
+                    }
+                }
+                keys
+            }
+            GamepadEvent::Disconnected(id) => {
+                let mut released = vec![];
+                self.stick_keys.retain(|&(pad, key)| {
+                    if pad == id {
+                        released.push((key, false));
+                    }
+                    pad != id
+                });
+                self.button_keys.retain(|&(pad, _), &mut key| {
+                    if pad == id {
+                        released.push((key, false));
+                    }
+                    pad != id
+                });
+                released
+            }
+            GamepadEvent::Connected(_) => vec![],
+        }
+    }
+}
+
+/// A gamepad backend paired with the key mapping applied to its events.
+pub struct GamepadInput {
+    backend: Box<dyn GamepadBackend>,
+    pub key_map: GamepadKeyMap,
+}
+
+impl GamepadInput {
+    pub fn new(backend: Box<dyn GamepadBackend>) -> GamepadInput {
+        GamepadInput {
+            backend,
+            key_map: GamepadKeyMap::default(),
+        }
+    }
+
+    /// Poll the backend, returning each gamepad event followed by the key events it maps to.
+    pub fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = vec![];
+        for event in self.backend.poll() {
+            events.push(InputEvent::Gamepad(event));
+            events.extend(
+                self.key_map
+                    .map(&event)
+                    .into_iter()
+                    .map(|(key, pressed)| InputEvent::Key { key, pressed }),
+            );
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PAD: GamepadId = GamepadId(0);
+
+    #[test]
+    fn test_virtual_gamepad_polls_one_frame_at_a_time() {
+        let mut pad = VirtualGamepad::new(PAD)
+            .connect()
+            .idle(1)
+            .press(GamepadButton::South);
+
+        assert_eq!(pad.poll(), vec![GamepadEvent::Connected(PAD)]);
+        assert_eq!(pad.poll(), vec![]);
+        assert_eq!(
+            pad.poll(),
+            vec![GamepadEvent::Button {
+                id: PAD,
+                button: GamepadButton::South,
+                pressed: true
+            }]
+        );
+        assert!(pad.is_finished());
+        assert_eq!(pad.poll(), vec![]);
+    }
+
+    #[test]
+    fn test_gamepads_track_state() {
+        let mut pads = Gamepads::new();
+        pads.apply(&GamepadEvent::Connected(PAD));
+        pads.apply(&GamepadEvent::Button {
+            id: PAD,
+            button: GamepadButton::North,
+            pressed: true,
+        });
+        pads.apply(&GamepadEvent::Axis {
+            id: PAD,
+            axis: GamepadAxis::RightTrigger,
+            value: 0.75,
+        });
+
+        let state = pads.get(PAD).unwrap();
+        assert!(state.is_pressed(GamepadButton::North));
+        assert!(!state.is_pressed(GamepadButton::South));
+        assert_eq!(state.axis(GamepadAxis::RightTrigger), 0.75);
+
+        pads.apply(&GamepadEvent::Disconnected(PAD));
+        assert!(pads.get(PAD).is_none());
+    }
+
+    #[test]
+    fn test_key_map_stick_press_and_release() {
+        let mut map = GamepadKeyMap::default();
+        let axis = |value| GamepadEvent::Axis {
+            id: PAD,
+            axis: GamepadAxis::LeftStickX,
+            value,
+        };
+
+        assert_eq!(map.map(&axis(0.2)), vec![]);
+        assert_eq!(map.map(&axis(0.9)), vec![(Key::Right, true)]);
+        assert_eq!(map.map(&axis(0.8)), vec![]);
+        assert_eq!(
+            map.map(&axis(-1.0)),
+            vec![(Key::Left, true), (Key::Right, false)]
+        );
+        assert_eq!(
+            map.map(&GamepadEvent::Disconnected(PAD)),
+            vec![(Key::Left, false)]
+        );
+    }
+
+    #[test]
+    fn test_key_map_releases_buttons_on_disconnect() {
+        let mut map = GamepadKeyMap::default();
+        let button = |id, button, pressed| GamepadEvent::Button {
+            id,
+            button,
+            pressed,
+        };
+
+        assert_eq!(
+            map.map(&button(PAD, GamepadButton::DPadUp, true)),
+            vec![(Key::Up, true)]
+        );
+        map.map(&button(PAD, GamepadButton::South, true));
+        map.map(&button(PAD, GamepadButton::South, false));
+        map.map(&button(GamepadId(1), GamepadButton::DPadLeft, true));
+        assert_eq!(
+            map.map(&GamepadEvent::Disconnected(PAD)),
+            vec![(Key::Up, false)]
+        );
+        assert_eq!(map.map(&GamepadEvent::Disconnected(PAD)), vec![]);
+        assert_eq!(
+            map.map(&GamepadEvent::Disconnected(GamepadId(1))),
+            vec![(Key::Left, false)]
+        );
+    }
+
+    #[test]
+    fn test_gamepad_input_emits_gamepad_then_key_events() {
+        let mut input = GamepadInput::new(Box::new(
+            VirtualGamepad::new(PAD).press(GamepadButton::DPadLeft),
+        ));
+        let pressed = GamepadEvent::Button {
+            id: PAD,
+            button: GamepadButton::DPadLeft,
+            pressed: true,
+        };
+        assert_eq!(
+            input.poll(),
+            vec![
+                InputEvent::Gamepad(pressed),
+                InputEvent::Key {
+                    key: Key::Left,
+                    pressed: true
+                }
+            ]
+        );
+    }
+}
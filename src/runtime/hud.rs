@@ -0,0 +1,162 @@
+//! Screen-space overlay drawn over the scene by the window runtime.
+//!
+//! Each frame the runtime clears a [`Hud`] and passes it to
+//! [`GameHandler::hud`](crate::runtime::GameHandler::hud), which fills it with text and
+//! rectangles. Positions and sizes are in pixels from the top-left corner of the window, and
+//! commands are drawn in the order they were added.
+
+use crate::runtime::debug_draw::DebugBox;
+use cgmath::Vector3;
+
+/// Scale of the built-in 6x10 font used by [`Hud::text`] unless changed.
+pub const DEFAULT_TEXT_SCALE: f32 = 2.0;
+
+/// Gap in pixels between the debug lines and the edge of the screen.
+const DEBUG_MARGIN: f32 = 4.0;
+
+/// One thing to draw on the overlay.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HudCommand {
+    /// A line of text in the built-in font, with its top-left corner at `position`. Each
+    /// glyph is 6x10 pixels multiplied by `scale`.
+    Text {
+        position: (f32, f32),
+        text: String,
+        rgb: Vector3<f32>,
+        scale: f32,
+    },
+    /// A filled rectangle with its top-left corner at `position`.
+    Rect {
+        position: (f32, f32),
+        size: (f32, f32),
+        rgb: Vector3<f32>,
+    },
+}
+
+/// The overlay draw list for one frame.
+#[derive(Clone, Debug)]
+pub struct Hud {
+    commands: Vec<HudCommand>,
+    text_scale: f32,
+}
+
+impl Default for Hud {
+    fn default() -> Hud {
+        Hud {
+            commands: vec![],
+            text_scale: DEFAULT_TEXT_SCALE,
+        }
+    }
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Hud::default()
+    }
+
+    /// Draw `text` with its top-left corner at `(x, y)`.
+    pub fn text(&mut self, x: f32, y: f32, text: impl Into<String>, rgb: Vector3<f32>) {
+        self.commands.push(HudCommand::Text {
+            position: (x, y),
+            text: text.into(),
+            rgb,
+            scale: self.text_scale,
+        });
+    }
+
+    /// Fill a rectangle with its top-left corner at `(x, y)`.
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, rgb: Vector3<f32>) {
+        self.commands.push(HudCommand::Rect {
+            position: (x, y),
+            size: (width, height),
+            rgb,
+        });
+    }
+
+    /// Scale text added after this call.
+    pub fn set_text_scale(&mut self, scale: f32) {
+        self.text_scale = scale;
+    }
+
+    /// Size in pixels of a glyph at the current text scale.
+    pub fn glyph_size(&self) -> (f32, f32) {
+        (
+            crate::font::GLYPH_WIDTH as f32 * self.text_scale,
+            crate::font::GLYPH_HEIGHT as f32 * self.text_scale,
+        )
+    }
+
+    pub fn commands(&self) -> &[HudCommand] {
+        &self.commands
+    }
+
+    /// Draw each line of `debug` along the bottom of a screen `screen_height` pixels high,
+    /// black on a white strip so it stays readable over the scene.
+    pub(crate) fn add_debug_lines(&mut self, debug: &str, screen_height: f32) {
+        self.set_text_scale(DEFAULT_TEXT_SCALE);
+        let (glyph_width, glyph_height) = self.glyph_size();
+        let line_height = glyph_height + DEBUG_MARGIN;
+        let lines: Vec<&str> = debug.lines().collect();
+        for (i, line) in lines.iter().enumerate() {
+            let y = screen_height - (lines.len() - i) as f32 * line_height;
+            let width = line.chars().count() as f32 * glyph_width + DEBUG_MARGIN * 2.0;
+            self.rect(0.0, y, width, line_height, Vector3::new(1.0, 1.0, 1.0));
+            self.text(
+                DEBUG_MARGIN,
+                y + DEBUG_MARGIN / 2.0,
+                *line,
+                Vector3::new(0.0, 0.0, 0.0),
+            );
+        }
+    }
+
+    /// Draw the runtime's pause and time scale status in the top-right corner of a screen
+    /// `screen_width` pixels wide.
+    pub(crate) fn add_status(&mut self, status: &str, screen_width: f32) {
+        self.set_text_scale(DEFAULT_TEXT_SCALE);
+        let (glyph_width, glyph_height) = self.glyph_size();
+        let width = status.chars().count() as f32 * glyph_width + DEBUG_MARGIN * 2.0;
+        let x = screen_width - width;
+        self.rect(
+            x,
+            0.0,
+            width,
+            glyph_height + DEBUG_MARGIN,
+            Vector3::new(1.0, 1.0, 1.0),
+        );
+        self.text(
+            x + DEBUG_MARGIN,
+            DEBUG_MARGIN / 2.0,
+            status,
+            Vector3::new(0.0, 0.0, 0.0),
+        );
+    }
+
+    /// Label each collision debug box with its entity id just above its top-left corner,
+    /// red if it collided. `world_to_screen` maps world positions to pixels.
+    pub(crate) fn add_debug_labels(
+        &mut self,
+        boxes: &[DebugBox],
+        world_to_screen: impl Fn(f32, f32) -> Option<(f32, f32)>,
+    ) {
+        self.set_text_scale(DEFAULT_TEXT_SCALE);
+        let glyph_height = self.glyph_size().1;
+        for debug_box in boxes {
+            let Some((x, y)) = world_to_screen(debug_box.x.start, debug_box.y.end) else {
+                continue;
+            };
+            let rgb = if debug_box.colliding {
+                Vector3::new(1.0, 0.0, 0.0)
+            } else {
+                Vector3::new(0.0, 0.8, 0.0)
+            };
+            self.text(x, y - glyph_height, debug_box.id.0.to_string(), rgb);
+        }
+    }
+
+    /// Remove every command and reset the text scale, keeping the allocation.
+    pub fn clear(&mut self) {
+        self.commands.clear();
+        self.text_scale = DEFAULT_TEXT_SCALE;
+    }
+}
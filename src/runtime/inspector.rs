@@ -0,0 +1,472 @@
+//! Entity inspector and live editing console.
+//!
+//! Both runtimes toggle the inspector with F1. While it is open, typed text goes to its
+//! console instead of the game, Up and Down select the previous or next entity, and in the
+//! window clicking an entity selects it. The panel lists the scene's entities with the
+//! selected one's components underneath. Editing needs
+//! [`GameHandler::scene_mut`](crate::runtime::GameHandler::scene_mut).
+//!
+//! Console commands:
+//!
+//! ```text
+//! select <id>
+//! spawn [x y]                  a 1x1 '#' entity, selected
+//! despawn [id]                 the selected entity by default
+//! set position|velocity|size <x> <y>
+//! set char <c>
+//! set rgb <r> <g> <b>
+//! set layer <n>
+//! pause                        pause or resume
+//! step [frames]                run single frames while paused
+//! speed <scale>                scale dt, e.g. 0.5 for slow motion
+//! ```
+
+use crate::runtime::hud::Hud;
+use crate::runtime::{GameHandler, RuntimeState, TextInput};
+use crate::scene::{
+    Components, Entity, EntityId, PositionComponent, RenderComponent, Scene, SizeComponent,
+    VelocityComponent,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use cgmath::Vector3;
+use std::str::FromStr;
+
+/// Most entities listed at once; the list scrolls to keep the selected entity visible.
+const MAX_LISTED: usize = 10;
+/// Console output lines kept.
+const MAX_OUTPUT: usize = 4;
+/// Gap in pixels around the panel's text in the window.
+const PANEL_MARGIN: f32 = 4.0;
+
+#[derive(Debug, Default)]
+pub(crate) struct Inspector {
+    open: bool,
+    selected: Option<EntityId>,
+    input: String,
+    output: Vec<String>,
+}
+
+impl Inspector {
+    pub(crate) fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub(crate) fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    /// Select the entity `offset` places after the selected one in `scene`, wrapping around.
+    pub(crate) fn select_next(&mut self, scene: &Scene, offset: isize) {
+        let entities = scene.entities();
+        if entities.is_empty() {
+            self.selected = None;
+            return;
+        }
+        let index = match self.selected_index(scene) {
+            Some(index) => (index as isize + offset).rem_euclid(entities.len() as isize),
+            None => 0,
+        };
+        self.selected = Some(entities[index as usize].id);
+    }
+
+    /// Select the topmost entity covering the world position `(x, y)`, if any.
+    pub(crate) fn select_at(&mut self, scene: &Scene, x: f32, y: f32) {
+        let mut entities: Vec<&Entity> = scene.entities().iter().collect();
+        crate::scene::sort_by_layer(&mut entities);
+        let hit = entities.into_iter().rev().find(|entity| {
+            let (Some(position), Some(size)) = (entity.components.position, entity.components.size)
+            else {
+                return false;
+            };
+            (position.x..position.x + size.x).contains(&x)
+                && (position.y..position.y + size.y).contains(&y)
+        });
+        if let Some(entity) = hit {
+            self.selected = Some(entity.id);
+        }
+    }
+
+    /// Edit the console line, running it on Enter.
+    pub(crate) fn handle_text(
+        &mut self,
+        input: TextInput,
+        game: &mut dyn GameHandler,
+        runtime_state: &mut RuntimeState,
+    ) {
+        match input {
+            TextInput::Char(c) => self.input.push(c),
+            TextInput::Backspace => {
+                self.input.pop();
+            }
+            TextInput::Enter => {
+                let command = std::mem::take(&mut self.input);
+                if command.trim().is_empty() {
+                    return;
+                }
+                self.print(format!("> {}", command));
+                match self.execute(&command, game, runtime_state) {
+                    Ok(message) if message.is_empty() => {}
+                    Ok(message) => self.print(message),
+                    Err(e) => self.print(format!("{:#}", e)),
+                }
+            }
+        }
+    }
+
+    fn print(&mut self, line: String) {
+        self.output.push(line);
+        if self.output.len() > MAX_OUTPUT {
+            self.output.remove(0);
+        }
+    }
+
+    /// Run a console command, returning a message to show.
+    pub(crate) fn execute(
+        &mut self,
+        command: &str,
+        game: &mut dyn GameHandler,
+        runtime_state: &mut RuntimeState,
+    ) -> Result<String> {
+        let mut words = command.split_whitespace();
+        let Some(name) = words.next() else {
+            return Ok(String::new());
+        };
+        let args: Vec<&str> = words.collect();
+        match name {
+            "select" => {
+                let id = EntityId(parse(&args, 0, "id")?);
+                if game.scene().get_entity_by_id(id).is_none() {
+                    bail!("No entity {}", id.0);
+                }
+                self.selected = Some(id);
+                Ok(String::new())
+            }
+            "spawn" => {
+                let position = match args.len() {
+                    0 => PositionComponent { x: 0.0, y: 0.0 },
+                    _ => PositionComponent {
+                        x: parse(&args, 0, "x")?,
+                        y: parse(&args, 1, "y")?,
+                    },
+                };
+                let mut components = Components::new();
+                components.position = Some(position);
+                components.velocity = Some(VelocityComponent { x: 0.0, y: 0.0 });
+                components.size = Some(SizeComponent { x: 1.0, y: 1.0 });
+                components.render = Some(default_render());
+                let id = scene_mut(game)?.add_entity_from_components(components);
+                self.selected = Some(id);
+                Ok(format!("Spawned {}", id.0))
+            }
+            "despawn" => {
+                let id = match args.first() {
+                    Some(_) => EntityId(parse(&args, 0, "id")?),
+                    None => self.selected_id()?,
+                };
+                if scene_mut(game)?.remove_entity(id).is_none() {
+                    bail!("No entity {}", id.0);
+                }
+                if self.selected == Some(id) {
+                    self.selected = None;
+                }
+                Ok(format!("Despawned {}", id.0))
+            }
+            "set" => {
+                let id = self.selected_id()?;
+                let field = args.first().ok_or_else(|| anyhow!("Missing field"))?;
+                let entity = scene_mut(game)?
+                    .get_entity_by_id_mut(id)
+                    .ok_or_else(|| anyhow!("No entity {}", id.0))?;
+                set_field(&mut entity.components, field, &args[1..])?;
+                Ok(String::new())
+            }
+            "pause" => {
+                let paused = !runtime_state.is_paused();
+                runtime_state.set_paused(game, paused);
+                Ok(if paused { "Paused" } else { "Resumed" }.to_string())
+            }
+            "step" => {
+                let frames = match args.first() {
+                    Some(_) => parse(&args, 0, "frames")?,
+                    None => 1,
+                };
+                runtime_state.set_paused(game, true);
+                runtime_state.step(frames);
+                Ok(String::new())
+            }
+            "speed" => {
+                runtime_state.set_time_scale(parse(&args, 0, "scale")?)?;
+                Ok(format!("Time scale x{}", runtime_state.time_scale()))
+            }
+            _ => bail!("Unknown command {}", name),
+        }
+    }
+
+    fn selected_id(&self) -> Result<EntityId> {
+        self.selected.ok_or_else(|| anyhow!("No entity selected"))
+    }
+
+    fn selected_index(&self, scene: &Scene) -> Option<usize> {
+        let selected = self.selected?;
+        scene.entities().iter().position(|e| e.id == selected)
+    }
+
+    /// The panel's text: the entity list, the selected entity's components, the console
+    /// output and the line being typed.
+    pub(crate) fn lines(&self, scene: &Scene) -> Vec<String> {
+        let entities = scene.entities();
+        let mut lines = vec![format!("Inspector (F1): {} entities", entities.len())];
+
+        let first = self
+            .selected_index(scene)
+            .map_or(0, |index| index.saturating_sub(MAX_LISTED / 2))
+            .min(entities.len().saturating_sub(MAX_LISTED));
+        for entity in entities.iter().skip(first).take(MAX_LISTED) {
+            let marker = if Some(entity.id) == self.selected {
+                '>'
+            } else {
+                ' '
+            };
+            let character = entity
+                .components
+                .render
+                .map_or(' ', |render| render.ascii_character);
+            let position = entity
+                .components
+                .position
+                .map_or(String::new(), |p| format!(" ({:.1}, {:.1})", p.x, p.y));
+            lines.push(format!(
+                "{}{:>4} {}{}",
+                marker, entity.id.0, character, position
+            ));
+        }
+
+        if let Some(entity) = self.selected.and_then(|id| scene.get_entity_by_id(id)) {
+            lines.push(String::new());
+            lines.extend(describe(entity));
+        }
+        lines.push(String::new());
+        lines.extend(self.output.iter().cloned());
+        lines.push(format!(": {}_", self.input));
+        lines
+    }
+
+    /// Draw the panel in the top-left corner of the window overlay.
+    pub(crate) fn draw(&self, hud: &mut Hud, scene: &Scene) {
+        let lines = self.lines(scene);
+        hud.set_text_scale(crate::runtime::hud::DEFAULT_TEXT_SCALE);
+        let (glyph_width, glyph_height) = hud.glyph_size();
+        let columns = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        hud.rect(
+            0.0,
+            0.0,
+            columns as f32 * glyph_width + PANEL_MARGIN * 2.0,
+            lines.len() as f32 * glyph_height + PANEL_MARGIN * 2.0,
+            Vector3::new(0.0, 0.0, 0.0),
+        );
+        for (i, line) in lines.into_iter().enumerate() {
+            hud.text(
+                PANEL_MARGIN,
+                PANEL_MARGIN + i as f32 * glyph_height,
+                line,
+                Vector3::new(1.0, 1.0, 1.0),
+            );
+        }
+    }
+}
+
+/// Each of an entity's components on its own line.
+fn describe(entity: &Entity) -> Vec<String> {
+    let components = &entity.components;
+    let mut lines = vec![format!("Entity {}", entity.id.0)];
+    if let Some(p) = components.position {
+        lines.push(format!("position {:.2} {:.2}", p.x, p.y));
+    }
+    if let Some(v) = components.velocity {
+        lines.push(format!("velocity {:.2} {:.2}", v.x, v.y));
+    }
+    if let Some(s) = components.size {
+        lines.push(format!("size     {:.2} {:.2}", s.x, s.y));
+    }
+    if let Some(r) = components.render {
+        lines.push(format!(
+            "render   '{}' rgb {:.2} {:.2} {:.2} layer {}",
+            r.ascii_character, r.rgb.x, r.rgb.y, r.rgb.z, r.layer
+        ));
+    }
+    if let Some(s) = components.sprite {
+        lines.push(format!(
+            "sprite   atlas {} at {} {} size {} {}",
+            s.atlas.0, s.x, s.y, s.width, s.height
+        ));
+    }
+    if components.camera_follow.is_some() {
+        lines.push("camera follow".to_string());
+    }
+    lines
+}
+
+fn default_render() -> RenderComponent {
+    RenderComponent {
+        ascii_character: '#',
+        rgb: Vector3::new(1.0, 1.0, 1.0),
+        layer: 0,
+    }
+}
+
+/// Set a component field from console arguments, adding the component if it is missing.
+fn set_field(components: &mut Components, field: &str, args: &[&str]) -> Result<()> {
+    match field {
+        "position" | "pos" => {
+            components.position = Some(PositionComponent {
+                x: parse(args, 0, "x")?,
+                y: parse(args, 1, "y")?,
+            });
+        }
+        "velocity" | "vel" => {
+            components.velocity = Some(VelocityComponent {
+                x: parse(args, 0, "x")?,
+                y: parse(args, 1, "y")?,
+            });
+        }
+        "size" => {
+            components.size = Some(SizeComponent {
+                x: parse(args, 0, "x")?,
+                y: parse(args, 1, "y")?,
+            });
+        }
+        "char" => {
+            let c = args
+                .first()
+                .and_then(|arg| arg.chars().next())
+                .ok_or_else(|| anyhow!("Missing char"))?;
+            components
+                .render
+                .get_or_insert_with(default_render)
+                .ascii_character = c;
+        }
+        "rgb" => {
+            components.render.get_or_insert_with(default_render).rgb = Vector3::new(
+                parse(args, 0, "r")?,
+                parse(args, 1, "g")?,
+                parse(args, 2, "b")?,
+            );
+        }
+        "layer" => {
+            components.render.get_or_insert_with(default_render).layer = parse(args, 0, "layer")?;
+        }
+        _ => bail!("Unknown field {}", field),
+    }
+    Ok(())
+}
+
+fn parse<T: FromStr>(args: &[&str], index: usize, name: &str) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let arg = args.get(index).ok_or_else(|| anyhow!("Missing {}", name))?;
+    arg.parse()
+        .with_context(|| format!("Invalid {} \"{}\"", name, arg))
+}
+
+fn scene_mut(game: &mut dyn GameHandler) -> Result<&mut Scene> {
+    game.scene_mut()
+        .ok_or_else(|| anyhow!("The game doesn't allow editing its scene"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::Key;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct EditableGame {
+        scene: Scene,
+    }
+
+    impl GameHandler for EditableGame {
+        fn start_game(&mut self) {}
+        fn next(&mut self, _dt: Duration) {}
+        fn scene(&self) -> &Scene {
+            &self.scene
+        }
+        fn scene_mut(&mut self) -> Option<&mut Scene> {
+            Some(&mut self.scene)
+        }
+        fn debug_str(&self) -> Option<String> {
+            None
+        }
+        fn handle_key(&mut self, _key: Key, _pressed: bool) -> bool {
+            false
+        }
+    }
+
+    fn run(inspector: &mut Inspector, game: &mut EditableGame, command: &str) -> Result<String> {
+        inspector.execute(command, game, &mut RuntimeState::default())
+    }
+
+    #[test]
+    fn test_spawn_set_and_despawn() {
+        let mut game = EditableGame::default();
+        let mut inspector = Inspector::default();
+
+        assert_eq!(
+            run(&mut inspector, &mut game, "spawn 2 3").unwrap(),
+            "Spawned 0"
+        );
+        run(&mut inspector, &mut game, "set vel 1.5 -2").unwrap();
+        run(&mut inspector, &mut game, "set char @").unwrap();
+        let entity = game.scene.get_entity_by_id(EntityId(0)).unwrap();
+        assert_eq!(entity.components.position.unwrap().x, 2.0);
+        assert_eq!(entity.components.velocity.unwrap().y, -2.0);
+        assert_eq!(entity.components.render.unwrap().ascii_character, '@');
+
+        assert!(run(&mut inspector, &mut game, "set size 1").is_err());
+        assert!(run(&mut inspector, &mut game, "set colour 1 2").is_err());
+        assert!(run(&mut inspector, &mut game, "select 5").is_err());
+
+        run(&mut inspector, &mut game, "despawn").unwrap();
+        assert!(game.scene.entities().is_empty());
+        assert!(run(&mut inspector, &mut game, "set pos 0 0").is_err());
+    }
+
+    #[test]
+    fn test_console_line_and_panel() {
+        let mut game = EditableGame::default();
+        let mut inspector = Inspector::default();
+        let mut state = RuntimeState::default();
+        for c in "spawn 1 1".chars() {
+            inspector.handle_text(TextInput::Char(c), &mut game, &mut state);
+        }
+        inspector.handle_text(TextInput::Enter, &mut game, &mut state);
+        for c in "step 2".chars() {
+            inspector.handle_text(TextInput::Char(c), &mut game, &mut state);
+        }
+        inspector.handle_text(TextInput::Enter, &mut game, &mut state);
+        inspector.handle_text(TextInput::Char('x'), &mut game, &mut state);
+        assert!(state.is_paused());
+
+        let lines = inspector.lines(&game.scene);
+        assert_eq!(lines[0], "Inspector (F1): 1 entities");
+        assert_eq!(lines[1], ">   0 # (1.0, 1.0)");
+        assert!(lines.contains(&"position 1.00 1.00".to_string()));
+        assert!(lines.contains(&"> spawn 1 1".to_string()));
+        assert_eq!(lines.last().unwrap(), ": x_");
+    }
+
+    #[test]
+    fn test_select_by_position_and_cycling() {
+        let mut game = EditableGame::default();
+        let mut inspector = Inspector::default();
+        run(&mut inspector, &mut game, "spawn 0 0").unwrap();
+        run(&mut inspector, &mut game, "spawn 5 0").unwrap();
+
+        inspector.select_at(&game.scene, 0.5, 0.5);
+        assert_eq!(inspector.selected, Some(EntityId(0)));
+        inspector.select_at(&game.scene, 3.0, 0.5);
+        assert_eq!(inspector.selected, Some(EntityId(0)));
+        inspector.select_next(&game.scene, -1);
+        assert_eq!(inspector.selected, Some(EntityId(1)));
+    }
+}
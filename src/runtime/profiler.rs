@@ -0,0 +1,285 @@
+//! Frame timing statistics and a lightweight profiler.
+//!
+//! Every runtime keeps a [`Profiler`] with the time between frames and how long named
+//! scopes took: `update` ([`GameHandler::next`](crate::runtime::GameHandler::next)), any
+//! scopes the game times itself in
+//! [`GameHandler::next_profiled`](crate::runtime::GameHandler::next_profiled), and `render`
+//! in the terminal and software runtimes or `render-prep` and `gpu-submit` in the window. The
+//! latest [`WINDOW`] samples of each are kept, and games read them in
+//! [`GameHandler::on_profile`](crate::runtime::GameHandler::on_profile).
+//!
+//! With tracing on, every sample is also kept as an event and can be written as Chrome
+//! trace JSON, which `chrome://tracing` or [Perfetto](https://ui.perfetto.dev) can open.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Number of samples kept for each statistic.
+pub const WINDOW: usize = 300;
+
+/// Summary of a set of durations.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Stats {
+    pub samples: usize,
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+impl Stats {
+    pub fn from_samples(samples: &[Duration]) -> Stats {
+        if samples.is_empty() {
+            return Stats::default();
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let percentile = |p: f32| sorted[((sorted.len() - 1) as f32 * p).round() as usize];
+        Stats {
+            samples: sorted.len(),
+            min: sorted[0],
+            avg: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            max: sorted[sorted.len() - 1],
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+        }
+    }
+
+    /// Frames per second for frame times with this average, or 0 without samples.
+    pub fn fps(&self) -> f32 {
+        if self.avg.is_zero() {
+            0.0
+        } else {
+            1.0 / self.avg.as_secs_f32()
+        }
+    }
+}
+
+/// A timed scope kept for the Chrome trace.
+#[derive(Debug, Clone, PartialEq)]
+struct TraceEvent {
+    name: &'static str,
+    /// Since the profiler was created.
+    start: Duration,
+    duration: Duration,
+}
+
+/// Rolling frame and scope timings.
+#[derive(Debug)]
+pub struct Profiler {
+    start: Instant,
+    frames: VecDeque<Duration>,
+    /// Samples of each scope, in the order the scopes were first recorded.
+    scopes: Vec<(&'static str, VecDeque<Duration>)>,
+    trace: Option<Vec<TraceEvent>>,
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            start: Instant::now(),
+            frames: VecDeque::with_capacity(WINDOW),
+            scopes: vec![],
+            trace: None,
+        }
+    }
+
+    /// Keep every sample from now on for [`Profiler::chrome_trace`].
+    pub fn start_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    /// Record the time since the previous frame.
+    pub fn record_frame(&mut self, dt: Duration) {
+        push_sample(&mut self.frames, dt);
+        let start = Instant::now().checked_sub(dt).unwrap_or(self.start);
+        self.add_trace_event("frame", start, dt);
+    }
+
+    /// Run `f`, recording how long it took as the scope `name`.
+    pub fn time<R>(&mut self, name: &'static str, f: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let result = f();
+        self.record(name, start, start.elapsed());
+        result
+    }
+
+    /// Record that the scope `name` started at `start` and took `duration`.
+    pub fn record(&mut self, name: &'static str, start: Instant, duration: Duration) {
+        let samples = match self.scopes.iter().position(|(scope, _)| *scope == name) {
+            Some(index) => &mut self.scopes[index].1,
+            None => {
+                self.scopes.push((name, VecDeque::with_capacity(WINDOW)));
+                &mut self.scopes.last_mut().unwrap().1
+            }
+        };
+        push_sample(samples, duration);
+        self.add_trace_event(name, start, duration);
+    }
+
+    fn add_trace_event(&mut self, name: &'static str, start: Instant, duration: Duration) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceEvent {
+                name,
+                start: start.saturating_duration_since(self.start),
+                duration,
+            });
+        }
+    }
+
+    /// Statistics of the time between frames.
+    pub fn frame_stats(&self) -> Stats {
+        deque_stats(&self.frames)
+    }
+
+    /// Statistics of a scope, if it has been recorded.
+    pub fn scope_stats(&self, name: &str) -> Option<Stats> {
+        self.scopes
+            .iter()
+            .find(|(scope, _)| *scope == name)
+            .map(|(_, samples)| deque_stats(samples))
+    }
+
+    /// Names of the recorded scopes, in the order they were first recorded.
+    pub fn scope_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.scopes.iter().map(|(name, _)| *name)
+    }
+
+    /// One line with the frame rate and the average time of each scope, e.g. for
+    /// [`GameHandler::debug_str`](crate::runtime::GameHandler::debug_str).
+    pub fn summary(&self) -> String {
+        let frames = self.frame_stats();
+        let mut summary = format!(
+            "{:.0} fps {:.1}ms (max {:.1}ms)",
+            frames.fps(),
+            millis(frames.avg),
+            millis(frames.max)
+        );
+        for name in self.scope_names() {
+            let stats = self.scope_stats(name).unwrap_or_default();
+            let _ = write!(summary, " {} {:.2}ms", name, millis(stats.avg));
+        }
+        summary
+    }
+
+    /// The traced samples as Chrome trace JSON. Empty unless [`Profiler::start_trace`] was
+    /// called.
+    pub fn chrome_trace(&self) -> String {
+        let events: Vec<Value> = self
+            .trace
+            .iter()
+            .flatten()
+            .map(|event| {
+                json!({
+                    "name": event.name,
+                    "ph": "X",
+                    "ts": micros(event.start),
+                    "dur": micros(event.duration),
+                    "pid": 1,
+                    "tid": 1,
+                })
+            })
+            .collect();
+        json!({ "traceEvents": events, "displayTimeUnit": "ms" }).to_string()
+    }
+
+    /// Write [`Profiler::chrome_trace`] to `path`.
+    pub fn save_chrome_trace(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.chrome_trace())
+            .with_context(|| format!("Unable to write trace {}", path.display()))
+    }
+}
+
+fn push_sample(samples: &mut VecDeque<Duration>, sample: Duration) {
+    if samples.len() == WINDOW {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_nanos() as f64 / 1000.0
+}
+
+fn deque_stats(samples: &VecDeque<Duration>) -> Stats {
+    let (front, back) = samples.as_slices();
+    Stats::from_samples(&[front, back].concat())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_stats() {
+        let samples: Vec<Duration> = (1..=100).rev().map(ms).collect();
+        let stats = Stats::from_samples(&samples);
+        assert_eq!((stats.min, stats.max), (ms(1), ms(100)));
+        assert_eq!(stats.avg, Duration::from_micros(50_500));
+        assert_eq!((stats.p50, stats.p95, stats.p99), (ms(51), ms(95), ms(99)));
+        assert_eq!(Stats::from_samples(&[]).fps(), 0.0);
+        assert_eq!(Stats::from_samples(&[ms(20)]).fps(), 50.0);
+    }
+
+    #[test]
+    fn test_rolling_window_and_scopes() {
+        let mut profiler = Profiler::new();
+        for i in 0..WINDOW as u64 + 10 {
+            profiler.record_frame(ms(i));
+        }
+        let stats = profiler.frame_stats();
+        assert_eq!((stats.samples, stats.min), (WINDOW, ms(10)));
+
+        assert_eq!(profiler.time("update", || 7), 7);
+        profiler.record("collision", Instant::now(), ms(2));
+        profiler.record("collision", Instant::now(), ms(4));
+        assert_eq!(
+            profiler.scope_names().collect::<Vec<_>>(),
+            vec!["update", "collision"]
+        );
+        assert_eq!(profiler.scope_stats("collision").unwrap().avg, ms(3));
+        assert!(profiler.scope_stats("render").is_none());
+        assert!(profiler.summary().contains(" collision 3.00ms"));
+    }
+
+    #[test]
+    fn test_chrome_trace() {
+        let mut profiler = Profiler::new();
+        profiler.record("update", Instant::now(), ms(1));
+        assert_eq!(profiler.chrome_trace().matches("\"ph\"").count(), 0);
+
+        profiler.start_trace();
+        profiler.record_frame(ms(16));
+        profiler.record("update", Instant::now(), Duration::from_micros(1500));
+        let trace: Value = serde_json::from_str(&profiler.chrome_trace()).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["name"], "frame");
+        assert_eq!(events[0]["ph"], "X");
+        assert_eq!(events[1]["name"], "update");
+        assert_eq!(events[1]["dur"], 1500.0);
+    }
+}
@@ -0,0 +1,430 @@
+//! Input recording and deterministic replay.
+//!
+//! Runtimes can record every input event together with the frame it arrived in and the
+//! `dt` passed to [`GameHandler::next`]. Feeding a [`Recording`] back into a freshly
+//! created (and identically seeded) game reproduces the original run exactly.
+//!
+//! Recordings are stored as plain text, one entry per line:
+//!
+//! ```text
+//! hewn-recording 1
+//! key 0 Left 1
+//! start 0
+//! frame 0 16000000
+//! ```
+//!
+//! `frame <index> <dt in nanoseconds>` closes a frame; the events listed before it are
+//! applied before `next` is called for that frame. Gamepad events are written as
+//! `pad <index> <connect|disconnect|button|axis> <gamepad> ...` and text input as
+//! `text <index> <char <code point>|backspace|enter>`.
+
+use crate::runtime::gamepad::{GamepadAxis, GamepadButton, GamepadEvent, GamepadId};
+use crate::runtime::profiler::Profiler;
+use crate::runtime::{GameHandler, Key, TextInput};
+use anyhow::{anyhow, bail, Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const HEADER: &str = "hewn-recording 1";
+
+/// An input event delivered to a game by a runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Key { key: Key, pressed: bool },
+    Gamepad(GamepadEvent),
+    Text(TextInput),
+    StartGame,
+}
+
+impl InputEvent {
+    /// Deliver the event to a game.
+    pub fn apply(&self, game: &mut dyn GameHandler) {
+        match *self {
+            InputEvent::Key { key, pressed } => {
+                game.handle_key(key, pressed);
+            }
+            InputEvent::Gamepad(event) => {
+                game.handle_gamepad(event);
+            }
+            InputEvent::Text(input) => game.handle_text(input),
+            InputEvent::StartGame => game.start_game(),
+        }
+    }
+}
+
+/// The events of a single frame and the `dt` the frame was stepped with.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RecordedFrame {
+    pub events: Vec<InputEvent>,
+    pub dt: Duration,
+}
+
+/// A recorded session which can be saved, loaded and replayed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recording {
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn new() -> Recording {
+        Recording::default()
+    }
+
+    /// Load a recording from a file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Recording> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read recording {}", path.display()))?;
+        Recording::parse(&contents)
+    }
+
+    /// Save the recording to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_string())
+            .with_context(|| format!("Unable to write recording {}", path.display()))
+    }
+
+    /// Parse a recording from its text representation.
+    pub fn parse(contents: &str) -> Result<Recording> {
+        let mut lines = contents.lines().enumerate();
+        match lines.next() {
+            Some((_, header)) if header.trim() == HEADER => {}
+            _ => bail!("Missing recording header, expected \"{}\"", HEADER),
+        }
+
+        let mut recording = Recording::new();
+        let mut events = vec![];
+        for (line_number, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parsed = parse_line(line, recording.frames.len())
+                .with_context(|| format!("Invalid recording line {}", line_number + 1))?;
+            match parsed {
+                Line::Event(event) => events.push(event),
+                Line::Frame(dt) => recording.frames.push(RecordedFrame {
+                    events: std::mem::take(&mut events),
+                    dt,
+                }),
+            }
+        }
+        Ok(recording)
+    }
+
+    /// Feed every recorded event and frame time into a game, updating it through
+    /// [`GameHandler::next_profiled`] like the runtimes do.
+    pub fn replay(&self, game: &mut dyn GameHandler) {
+        let mut profiler = Profiler::new();
+        for frame in &self.frames {
+            for event in &frame.events {
+                event.apply(game);
+            }
+            game.next_profiled(frame.dt, &mut profiler);
+        }
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for (index, frame) in self.frames.iter().enumerate() {
+            for event in &frame.events {
+                match event {
+                    InputEvent::Key { key, pressed } => {
+                        writeln!(f, "key {} {:?} {}", index, key, *pressed as u8)?
+                    }
+                    InputEvent::Gamepad(event) => write_gamepad_event(f, index, event)?,
+                    InputEvent::Text(TextInput::Char(c)) => {
+                        writeln!(f, "text {} char {}", index, *c as u32)?
+                    }
+                    InputEvent::Text(TextInput::Backspace) => {
+                        writeln!(f, "text {} backspace", index)?
+                    }
+                    InputEvent::Text(TextInput::Enter) => writeln!(f, "text {} enter", index)?,
+                    InputEvent::StartGame => writeln!(f, "start {}", index)?,
+                }
+            }
+            writeln!(f, "frame {} {}", index, frame.dt.as_nanos())?;
+        }
+        Ok(())
+    }
+}
+
+fn write_gamepad_event(
+    f: &mut fmt::Formatter<'_>,
+    index: usize,
+    event: &GamepadEvent,
+) -> fmt::Result {
+    match event {
+        GamepadEvent::Connected(id) => writeln!(f, "pad {} connect {}", index, id.0),
+        GamepadEvent::Disconnected(id) => writeln!(f, "pad {} disconnect {}", index, id.0),
+        GamepadEvent::Button {
+            id,
+            button,
+            pressed,
+        } => writeln!(
+            f,
+            "pad {} button {} {:?} {}",
+            index, id.0, button, *pressed as u8
+        ),
+        GamepadEvent::Axis { id, axis, value } => {
+            writeln!(f, "pad {} axis {} {:?} {}", index, id.0, axis, value)
+        }
+    }
+}
+
+enum Line {
+    Event(InputEvent),
+    Frame(Duration),
+}
+
+fn parse_line(line: &str, expected_frame: usize) -> Result<Line> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let frame_index: usize = parts
+        .get(1)
+        .ok_or_else(|| anyhow!("Missing frame index"))?
+        .parse()?;
+    if frame_index != expected_frame {
+        bail!(
+            "Expected frame {} but found frame {}",
+            expected_frame,
+            frame_index
+        );
+    }
+
+    match parts.as_slice() {
+        ["key", _, key, pressed] => Ok(Line::Event(InputEvent::Key {
+            key: parse_key(key)?,
+            pressed: *pressed == "1",
+        })),
+        ["start", _] => Ok(Line::Event(InputEvent::StartGame)),
+        ["pad", _, "connect", id] => Ok(Line::Event(InputEvent::Gamepad(GamepadEvent::Connected(
+            GamepadId(id.parse()?),
+        )))),
+        ["pad", _, "disconnect", id] => Ok(Line::Event(InputEvent::Gamepad(
+            GamepadEvent::Disconnected(GamepadId(id.parse()?)),
+        ))),
+        ["pad", _, "button", id, button, pressed] => {
+            Ok(Line::Event(InputEvent::Gamepad(GamepadEvent::Button {
+                id: GamepadId(id.parse()?),
+                button: parse_button(button)?,
+                pressed: *pressed == "1",
+            })))
+        }
+        ["pad", _, "axis", id, axis, value] => {
+            Ok(Line::Event(InputEvent::Gamepad(GamepadEvent::Axis {
+                id: GamepadId(id.parse()?),
+                axis: parse_axis(axis)?,
+                value: value.parse()?,
+            })))
+        }
+        ["text", _, "char", code] => {
+            let c = char::from_u32(code.parse()?)
+                .ok_or_else(|| anyhow!("Invalid character code {}", code))?;
+            Ok(Line::Event(InputEvent::Text(TextInput::Char(c))))
+        }
+        ["text", _, "backspace"] => Ok(Line::Event(InputEvent::Text(TextInput::Backspace))),
+        ["text", _, "enter"] => Ok(Line::Event(InputEvent::Text(TextInput::Enter))),
+        ["frame", _, nanos] => Ok(Line::Frame(Duration::from_nanos(nanos.parse()?))),
+        _ => bail!("Unrecognised entry \"{}\"", line),
+    }
+}
+
+fn parse_key(key: &str) -> Result<Key> {
+    match key {
+        "Left" => Ok(Key::Left),
+        "Right" => Ok(Key::Right),
+        "Up" => Ok(Key::Up),
+        "Down" => Ok(Key::Down),
+        "Space" => Ok(Key::Space),
+        "Escape" => Ok(Key::Escape),
+        "Q" => Ok(Key::Q),
+        _ => bail!("Unknown key \"{}\"", key),
+    }
+}
+
+fn parse_button(button: &str) -> Result<GamepadButton> {
+    match button {
+        "South" => Ok(GamepadButton::South),
+        "East" => Ok(GamepadButton::East),
+        "West" => Ok(GamepadButton::West),
+        "North" => Ok(GamepadButton::North),
+        "DPadUp" => Ok(GamepadButton::DPadUp),
+        "DPadDown" => Ok(GamepadButton::DPadDown),
+        "DPadLeft" => Ok(GamepadButton::DPadLeft),
+        "DPadRight" => Ok(GamepadButton::DPadRight),
+        "LeftShoulder" => Ok(GamepadButton::LeftShoulder),
+        "RightShoulder" => Ok(GamepadButton::RightShoulder),
+        "LeftStick" => Ok(GamepadButton::LeftStick),
+        "RightStick" => Ok(GamepadButton::RightStick),
+        "Select" => Ok(GamepadButton::Select),
+        "Start" => Ok(GamepadButton::Start),
+        _ => bail!("Unknown gamepad button \"{}\"", button),
+    }
+}
+
+fn parse_axis(axis: &str) -> Result<GamepadAxis> {
+    match axis {
+        "LeftStickX" => Ok(GamepadAxis::LeftStickX),
+        "LeftStickY" => Ok(GamepadAxis::LeftStickY),
+        "RightStickX" => Ok(GamepadAxis::RightStickX),
+        "RightStickY" => Ok(GamepadAxis::RightStickY),
+        "LeftTrigger" => Ok(GamepadAxis::LeftTrigger),
+        "RightTrigger" => Ok(GamepadAxis::RightTrigger),
+        _ => bail!("Unknown gamepad axis \"{}\"", axis),
+    }
+}
+
+/// Records input events and frame times from a running game and saves them to a file.
+pub struct InputRecorder {
+    path: PathBuf,
+    recording: Recording,
+    pending_events: Vec<InputEvent>,
+}
+
+impl InputRecorder {
+    pub fn new(path: impl Into<PathBuf>) -> InputRecorder {
+        InputRecorder {
+            path: path.into(),
+            recording: Recording::new(),
+            pending_events: vec![],
+        }
+    }
+
+    /// Record an event for the current frame.
+    pub fn record(&mut self, event: InputEvent) {
+        self.pending_events.push(event);
+    }
+
+    /// Close the current frame after the game has been stepped by `dt`.
+    pub fn end_frame(&mut self, dt: Duration) {
+        self.recording.frames.push(RecordedFrame {
+            events: std::mem::take(&mut self.pending_events),
+            dt,
+        });
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// Write the frames recorded so far to the recorder's file.
+    pub fn save(&self) -> Result<()> {
+        self.recording.save(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scene::Scene;
+
+    #[derive(Default)]
+    struct CountingGame {
+        scene: Scene,
+        started: u32,
+        x: i32,
+        elapsed: Duration,
+        profiled_frames: u32,
+    }
+
+    impl GameHandler for CountingGame {
+        fn start_game(&mut self) {
+            self.started += 1;
+        }
+
+        fn next(&mut self, dt: Duration) {
+            self.elapsed += dt;
+        }
+
+        fn next_profiled(&mut self, dt: Duration, _profiler: &mut Profiler) {
+            self.profiled_frames += 1;
+            self.next(dt);
+        }
+
+        fn scene(&self) -> &Scene {
+            &self.scene
+        }
+
+        fn debug_str(&self) -> Option<String> {
+            None
+        }
+
+        fn handle_key(&mut self, key: Key, pressed: bool) -> bool {
+            match (key, pressed) {
+                (Key::Left, true) => self.x -= 1,
+                (Key::Right, true) => self.x += 1,
+                _ => return false,
+            }
+            true
+        }
+    }
+
+    fn sample_recording() -> Recording {
+        let mut recorder = InputRecorder::new("unused.rec");
+        recorder.record(InputEvent::StartGame);
+        recorder.record(InputEvent::Gamepad(GamepadEvent::Connected(GamepadId(1))));
+        recorder.record(InputEvent::Text(TextInput::Char(' ')));
+        recorder.record(InputEvent::Text(TextInput::Char('é')));
+        recorder.record(InputEvent::Text(TextInput::Backspace));
+        recorder.record(InputEvent::Text(TextInput::Enter));
+        recorder.end_frame(Duration::from_millis(16));
+        recorder.record(InputEvent::Gamepad(GamepadEvent::Axis {
+            id: GamepadId(1),
+            axis: GamepadAxis::LeftStickX,
+            value: -0.123_456_7,
+        }));
+        recorder.record(InputEvent::Gamepad(GamepadEvent::Button {
+            id: GamepadId(1),
+            button: GamepadButton::South,
+            pressed: true,
+        }));
+        recorder.record(InputEvent::Key {
+            key: Key::Right,
+            pressed: true,
+        });
+        recorder.record(InputEvent::Key {
+            key: Key::Right,
+            pressed: false,
+        });
+        recorder.end_frame(Duration::from_millis(17));
+        recorder.record(InputEvent::Key {
+            key: Key::Left,
+            pressed: true,
+        });
+        recorder.end_frame(Duration::from_nanos(16_666_667));
+        recorder.recording().clone()
+    }
+
+    #[test]
+    fn test_recording_round_trip() {
+        let recording = sample_recording();
+        let parsed = Recording::parse(&recording.to_string()).unwrap();
+        assert_eq!(recording, parsed);
+    }
+
+    #[test]
+    fn test_recording_rejects_out_of_order_frames() {
+        let contents = "hewn-recording 1\nframe 0 10\nkey 0 Left 1\nframe 1 10\n";
+        assert!(Recording::parse(contents).is_err());
+    }
+
+    #[test]
+    fn test_replay_feeds_events_and_frame_times() {
+        let recording = sample_recording();
+        let mut game = CountingGame::default();
+        recording.replay(&mut game);
+
+        assert_eq!(game.started, 1);
+        assert_eq!(game.x, 0);
+        assert_eq!(game.profiled_frames, 3);
+        assert_eq!(
+            game.elapsed,
+            Duration::from_millis(33) + Duration::from_nanos(16_666_667)
+        );
+    }
+}
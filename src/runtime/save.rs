@@ -0,0 +1,497 @@
+//! Save games in named slots. Needs the `serde` feature.
+//!
+//! A save holds a snapshot of the [`Scene`] (or no scene, e.g. for high scores) and any
+//! serializable game data, stored as JSON with the game's save version. Slots are files in a
+//! directory on native targets and `localStorage` entries in the browser.
+//!
+//! When components or game data change between releases, bump the version and add a
+//! migration from the old version with [`SaveSlots::add_migration`]. Loading an older save
+//! runs each migration in turn on the raw JSON before it is deserialized.
+
+use crate::runtime::GameHandler;
+use crate::scene::Scene;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Where save slots are kept.
+pub trait SaveStorage {
+    /// The contents of `slot`, or `None` if nothing is saved there.
+    fn read(&self, slot: &str) -> Result<Option<String>>;
+    fn write(&mut self, slot: &str, contents: &str) -> Result<()>;
+    /// Remove `slot`. Removing an empty slot is not an error.
+    fn delete(&mut self, slot: &str) -> Result<()>;
+    /// Names of the saved slots, sorted.
+    fn slots(&self) -> Result<Vec<String>>;
+}
+
+/// Slots saved as `<slot>.json` files in a directory, created on the first save.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct DirectoryStorage {
+    dir: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DirectoryStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> DirectoryStorage {
+        DirectoryStorage { dir: dir.into() }
+    }
+
+    /// `<data dir>/<game>/saves`, where the data directory is `$XDG_DATA_HOME`, `%APPDATA%`
+    /// or `~/.local/share`, falling back to the current directory.
+    pub fn for_game(game: &str) -> DirectoryStorage {
+        let data_dir = std::env::var_os("XDG_DATA_HOME")
+            .or_else(|| std::env::var_os("APPDATA"))
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })
+            .unwrap_or_else(|| PathBuf::from("."));
+        DirectoryStorage::new(data_dir.join(game).join("saves"))
+    }
+
+    fn path(&self, slot: &str) -> PathBuf {
+        self.dir.join(format!("{slot}.json"))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SaveStorage for DirectoryStorage {
+    fn read(&self, slot: &str) -> Result<Option<String>> {
+        let path = self.path(slot);
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Unable to read {}", path.display())),
+        }
+    }
+
+    fn write(&mut self, slot: &str, contents: &str) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Unable to create {}", self.dir.display()))?;
+        // Write then rename so a crash mid-save can't leave a truncated slot.
+        let path = self.path(slot);
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, contents)
+            .with_context(|| format!("Unable to write {}", partial.display()))?;
+        fs::rename(&partial, &path).with_context(|| format!("Unable to write {}", path.display()))
+    }
+
+    fn delete(&mut self, slot: &str) -> Result<()> {
+        let path = self.path(slot);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Unable to delete {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn slots(&self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Unable to read {}", self.dir.display()))
+            }
+        };
+        let mut slots = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                if let Some(slot) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    slots.push(slot.to_string());
+                }
+            }
+        }
+        slots.sort();
+        Ok(slots)
+    }
+}
+
+/// Slots saved in the browser's `localStorage` as `<game>/<slot>`.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    prefix: String,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorage {
+    pub fn for_game(game: &str) -> LocalStorage {
+        LocalStorage {
+            prefix: format!("{game}/"),
+        }
+    }
+
+    fn storage() -> Result<web_sys::Storage> {
+        web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or_else(|| anyhow!("localStorage is not available"))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn js_error(error: wasm_bindgen::JsValue) -> anyhow::Error {
+    anyhow!("localStorage error: {:?}", error)
+}
+
+#[cfg(target_arch = "wasm32")]
+impl SaveStorage for LocalStorage {
+    fn read(&self, slot: &str) -> Result<Option<String>> {
+        LocalStorage::storage()?
+            .get_item(&format!("{}{slot}", self.prefix))
+            .map_err(js_error)
+    }
+
+    fn write(&mut self, slot: &str, contents: &str) -> Result<()> {
+        LocalStorage::storage()?
+            .set_item(&format!("{}{slot}", self.prefix), contents)
+            .map_err(js_error)
+    }
+
+    fn delete(&mut self, slot: &str) -> Result<()> {
+        LocalStorage::storage()?
+            .remove_item(&format!("{}{slot}", self.prefix))
+            .map_err(js_error)
+    }
+
+    fn slots(&self) -> Result<Vec<String>> {
+        let storage = LocalStorage::storage()?;
+        let mut slots = vec![];
+        for i in 0..storage.length().map_err(js_error)? {
+            if let Some(key) = storage.key(i).map_err(js_error)? {
+                if let Some(slot) = key.strip_prefix(&self.prefix) {
+                    slots.push(slot.to_string());
+                }
+            }
+        }
+        slots.sort();
+        Ok(slots)
+    }
+}
+
+/// Slots kept in memory, e.g. for tests. Clones share the same slots.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    slots: Rc<RefCell<BTreeMap<String, String>>>,
+}
+
+impl SaveStorage for MemoryStorage {
+    fn read(&self, slot: &str) -> Result<Option<String>> {
+        Ok(self.slots.borrow().get(slot).cloned())
+    }
+
+    fn write(&mut self, slot: &str, contents: &str) -> Result<()> {
+        self.slots
+            .borrow_mut()
+            .insert(slot.to_string(), contents.to_string());
+        Ok(())
+    }
+
+    fn delete(&mut self, slot: &str) -> Result<()> {
+        self.slots.borrow_mut().remove(slot);
+        Ok(())
+    }
+
+    fn slots(&self) -> Result<Vec<String>> {
+        Ok(self.slots.borrow().keys().cloned().collect())
+    }
+}
+
+/// A loaded save.
+#[derive(Deserialize)]
+pub struct SaveGame<T> {
+    /// Always the current save version, as older saves are migrated when loaded.
+    pub version: u32,
+    pub scene: Option<Scene>,
+    pub data: T,
+}
+
+#[derive(Serialize)]
+struct SaveGameRef<'a, T> {
+    version: u32,
+    scene: Option<&'a Scene>,
+    data: &'a T,
+}
+
+/// Upgrades the JSON of a save, with `version`, `scene` and `data` fields, by one version.
+type Migration = Box<dyn Fn(&mut Value) -> Result<()>>;
+
+/// Named save slots for one game.
+pub struct SaveSlots {
+    storage: Box<dyn SaveStorage>,
+    version: u32,
+    migrations: HashMap<u32, Migration>,
+}
+
+impl SaveSlots {
+    /// Save slots in `storage`, saving at `version`.
+    pub fn new(storage: Box<dyn SaveStorage>, version: u32) -> SaveSlots {
+        SaveSlots {
+            storage,
+            version,
+            migrations: HashMap::new(),
+        }
+    }
+
+    /// Save slots in the platform's usual place: [`DirectoryStorage::for_game`] on native
+    /// targets or [`LocalStorage::for_game`] in the browser.
+    pub fn for_game(game: &str, version: u32) -> SaveSlots {
+        #[cfg(not(target_arch = "wasm32"))]
+        let storage = DirectoryStorage::for_game(game);
+        #[cfg(target_arch = "wasm32")]
+        let storage = LocalStorage::for_game(game);
+        SaveSlots::new(Box::new(storage), version)
+    }
+
+    /// The version saves are written at.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Upgrade saves from version `from` to `from + 1` when they are loaded. Versions
+    /// without a migration are loaded unchanged.
+    pub fn add_migration(
+        &mut self,
+        from: u32,
+        migration: impl Fn(&mut Value) -> Result<()> + 'static,
+    ) {
+        self.migrations.insert(from, Box::new(migration));
+    }
+
+    /// Save `scene`, if any, and `data` to `slot`, replacing what was there.
+    pub fn save<T: Serialize>(
+        &mut self,
+        slot: &str,
+        scene: Option<&Scene>,
+        data: &T,
+    ) -> Result<()> {
+        check_slot_name(slot)?;
+        let save = SaveGameRef {
+            version: self.version,
+            scene,
+            data,
+        };
+        let contents = serde_json::to_string_pretty(&save)
+            .with_context(|| format!("Unable to serialize save {slot}"))?;
+        self.storage.write(slot, &contents)
+    }
+
+    /// Save the game's scene and `data` to `slot`.
+    pub fn save_game<T: Serialize>(
+        &mut self,
+        slot: &str,
+        game: &dyn GameHandler,
+        data: &T,
+    ) -> Result<()> {
+        self.save(slot, Some(game.scene()), data)
+    }
+
+    /// Load `slot`, migrating it to the current version. `None` if the slot is empty.
+    pub fn load<T: DeserializeOwned>(&self, slot: &str) -> Result<Option<SaveGame<T>>> {
+        check_slot_name(slot)?;
+        let Some(contents) = self.storage.read(slot)? else {
+            return Ok(None);
+        };
+        let mut save: Value = serde_json::from_str(&contents)
+            .with_context(|| format!("Save {slot} is not valid JSON"))?;
+        self.migrate(&mut save)
+            .with_context(|| format!("Unable to migrate save {slot}"))?;
+        serde_json::from_value(save)
+            .map(Some)
+            .with_context(|| format!("Unable to load save {slot}"))
+    }
+
+    /// Load `slot` into a running game, replacing its scene, and return the game data.
+    /// `None` if the slot is empty. Fails if the save has no scene or the game doesn't
+    /// provide [`GameHandler::scene_mut`].
+    pub fn load_into<T: DeserializeOwned>(
+        &self,
+        slot: &str,
+        game: &mut dyn GameHandler,
+    ) -> Result<Option<T>> {
+        let Some(save) = self.load::<T>(slot)? else {
+            return Ok(None);
+        };
+        let scene = save
+            .scene
+            .ok_or_else(|| anyhow!("Save {slot} has no scene"))?;
+        let game_scene = game
+            .scene_mut()
+            .ok_or_else(|| anyhow!("The game's scene can't be replaced"))?;
+        *game_scene = scene;
+        Ok(Some(save.data))
+    }
+
+    /// Empty `slot`.
+    pub fn delete(&mut self, slot: &str) -> Result<()> {
+        check_slot_name(slot)?;
+        self.storage.delete(slot)
+    }
+
+    /// Names of the saved slots, sorted.
+    pub fn slots(&self) -> Result<Vec<String>> {
+        self.storage.slots()
+    }
+
+    fn migrate(&self, save: &mut Value) -> Result<()> {
+        let version = save
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| anyhow!("Missing version"))?;
+        if version > self.version as u64 {
+            bail!(
+                "Saved by a newer version ({}) than this one ({})",
+                version,
+                self.version
+            );
+        }
+        for from in version as u32..self.version {
+            if let Some(migration) = self.migrations.get(&from) {
+                migration(save).with_context(|| format!("Migration from version {from} failed"))?;
+            }
+            save["version"] = Value::from(from + 1);
+        }
+        Ok(())
+    }
+}
+
+/// Slot names become file names, so only allow letters, digits, `-`, `_` and spaces.
+fn check_slot_name(slot: &str) -> Result<()> {
+    let valid = slot
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ' '));
+    if slot.trim().is_empty() || !valid {
+        bail!("Invalid save slot name {:?}", slot);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::Key;
+    use crate::scene::{Components, EntityId, PositionComponent};
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct SavingGame {
+        scene: Scene,
+    }
+
+    impl GameHandler for SavingGame {
+        fn start_game(&mut self) {}
+
+        fn next(&mut self, _dt: Duration) {}
+
+        fn scene(&self) -> &Scene {
+            &self.scene
+        }
+
+        fn scene_mut(&mut self) -> Option<&mut Scene> {
+            Some(&mut self.scene)
+        }
+
+        fn debug_str(&self) -> Option<String> {
+            None
+        }
+
+        fn handle_key(&mut self, _key: Key, _pressed: bool) -> bool {
+            false
+        }
+    }
+
+    fn position(x: f32, y: f32) -> Components {
+        Components {
+            position: Some(PositionComponent { x, y }),
+            ..Components::new()
+        }
+    }
+
+    #[test]
+    fn test_save_and_load_into_game() {
+        let mut slots = SaveSlots::new(Box::new(MemoryStorage::default()), 1);
+        let mut game = SavingGame::default();
+        game.scene.add_entity_from_components(position(1.0, 2.0));
+        slots
+            .save_game("slot 1", &game, &(7u16, "level-2"))
+            .unwrap();
+        slots.save("scores", None, &[10u16, 5]).unwrap();
+        assert_eq!(slots.slots().unwrap(), vec!["scores", "slot 1"]);
+
+        let mut loaded = SavingGame::default();
+        let data: (u16, String) = slots.load_into("slot 1", &mut loaded).unwrap().unwrap();
+        assert_eq!(data, (7, "level-2".to_string()));
+        assert_eq!(loaded.scene.entities(), game.scene.entities());
+        assert_eq!(
+            loaded.scene.add_entity_from_components(Components::new()),
+            EntityId(1)
+        );
+
+        let scores = slots.load::<Vec<u16>>("scores").unwrap().unwrap();
+        assert!(scores.scene.is_none());
+        assert!(slots.load_into::<Vec<u16>>("scores", &mut loaded).is_err());
+        assert!(slots.load::<u16>("empty").unwrap().is_none());
+
+        slots.delete("scores").unwrap();
+        assert_eq!(slots.slots().unwrap(), vec!["slot 1"]);
+        assert!(slots.save("../escape", None, &0).is_err());
+    }
+
+    #[test]
+    fn test_migrations() {
+        let storage = MemoryStorage::default();
+        let mut old = SaveSlots::new(Box::new(storage.clone()), 1);
+        old.save("slot", None, &json!({"best": 12})).unwrap();
+
+        // Version 2 renamed `best` to `high_score`, and version 3 added `plays`.
+        let mut slots = SaveSlots::new(Box::new(storage.clone()), 3);
+        slots.add_migration(1, |save| {
+            let best = save["data"]["best"].take();
+            save["data"] = json!({ "high_score": best });
+            Ok(())
+        });
+        slots.add_migration(2, |save| {
+            save["data"]["plays"] = json!(0);
+            Ok(())
+        });
+        let save = slots.load::<Value>("slot").unwrap().unwrap();
+        assert_eq!(save.version, 3);
+        assert_eq!(save.data, json!({"high_score": 12, "plays": 0}));
+
+        slots.save("newer", None, &0).unwrap();
+        assert!(old.load::<u16>("newer").is_err());
+    }
+
+    #[test]
+    fn test_directory_storage() {
+        let dir = std::env::temp_dir().join(format!("hewn-save-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut slots = SaveSlots::new(Box::new(DirectoryStorage::new(&dir)), 1);
+        assert!(slots.slots().unwrap().is_empty());
+        assert!(slots.load::<u16>("best").unwrap().is_none());
+
+        slots.save("best", None, &42u16).unwrap();
+        assert!(dir.join("best.json").exists());
+        assert_eq!(slots.load::<u16>("best").unwrap().unwrap().data, 42);
+        assert_eq!(slots.slots().unwrap(), vec!["best"]);
+        slots.delete("best").unwrap();
+        slots.delete("best").unwrap();
+        assert!(slots.slots().unwrap().is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
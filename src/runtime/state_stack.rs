@@ -0,0 +1,483 @@
+//! A stack of game states for menus, gameplay and pause screens.
+//!
+//! Each [`GameState`] owns its own [`Scene`] and input handling. Only the top state is
+//! updated and receives input; it returns a [`Transition`] to push, pop or replace states.
+//! A transparent state (e.g. a pause overlay) is drawn over the states beneath it.
+//!
+//! [`StateStack`] implements [`GameHandler`], so it can be passed to any runtime in place
+//! of a single game.
+
+use crate::runtime::camera::CameraConfig;
+use crate::runtime::gamepad::GamepadEvent;
+use crate::runtime::hud::Hud;
+use crate::runtime::profiler::Profiler;
+use crate::runtime::{GameHandler, Key, TextInput};
+use crate::scene::Scene;
+use std::time::Duration;
+
+/// A change to the state stack requested by the top state.
+#[derive(Default)]
+pub enum Transition {
+    #[default]
+    None,
+    /// Leave the stack as it is and report the input as not handled, e.g. from
+    /// [`GameState::handle_key`] for keys the state doesn't use.
+    Ignored,
+    /// Push a new state on top of the current one.
+    Push(Box<dyn GameState>),
+    /// Remove the current state, returning to the one beneath it.
+    Pop,
+    /// Swap the current state for a new one.
+    Replace(Box<dyn GameState>),
+}
+
+/// A single state in a [`StateStack`].
+pub trait GameState {
+    /// Get the state's scene.
+    fn scene(&self) -> &Scene;
+
+    /// Mutable access to the scene for the inspector, see [`GameHandler::scene_mut`].
+    fn scene_mut(&mut self) -> Option<&mut Scene> {
+        None
+    }
+
+    /// Compute the next state of the scene.
+    fn next(&mut self, dt: Duration) -> Transition;
+
+    /// See [`GameHandler::next_profiled`]. By default it just calls `next`.
+    fn next_profiled(&mut self, dt: Duration, _profiler: &mut Profiler) -> Transition {
+        self.next(dt)
+    }
+
+    fn handle_key(&mut self, key: Key, pressed: bool) -> Transition;
+
+    /// Called for the runtime's start action (space in the terminal).
+    fn start(&mut self) -> Transition {
+        Transition::None
+    }
+
+    fn handle_gamepad(&mut self, _event: GamepadEvent) -> Transition {
+        Transition::Ignored
+    }
+
+    fn text_input_enabled(&self) -> bool {
+        false
+    }
+
+    fn handle_text(&mut self, _input: TextInput) -> Transition {
+        Transition::None
+    }
+
+    /// Get a string for debugging.
+    fn debug_str(&self) -> Option<String> {
+        None
+    }
+
+    fn camera(&self) -> CameraConfig {
+        CameraConfig::default()
+    }
+
+    /// Fill the overlay. Like scenes, the overlays of visible states are drawn bottom up.
+    fn hud(&self, _hud: &mut Hud) {}
+
+    /// Whether the states beneath this one should still be drawn.
+    fn is_transparent(&self) -> bool {
+        false
+    }
+
+    /// Called when the state becomes the top of the stack.
+    fn on_enter(&mut self) {}
+
+    /// Called when the state stops being the top of the stack.
+    fn on_leave(&mut self) {}
+
+    /// See [`GameHandler::time_scale`]. Only the top state's is used.
+    fn time_scale(&self) -> f32 {
+        1.0
+    }
+
+    /// See [`GameHandler::on_profile`]. Called on the top state.
+    fn on_profile(&mut self, _profiler: &Profiler) {}
+
+    /// See [`GameHandler::on_pause`]. Called on the top state.
+    fn on_pause(&mut self) {}
+
+    /// See [`GameHandler::on_resume`]. Called on the top state.
+    fn on_resume(&mut self) {}
+
+    /// See [`GameHandler::on_focus_changed`]. Called on the top state.
+    fn on_focus_changed(&mut self, _focused: bool) {}
+
+    /// See [`GameHandler::on_resize`]. Called on every state, so states beneath the top
+    /// one are laid out for the new size when they are returned to.
+    fn on_resize(&mut self, _width: u32, _height: u32) {}
+
+    /// See [`GameHandler::on_exit`]. Called on every state, top first.
+    fn on_exit(&mut self) {}
+}
+
+/// A stack of [`GameState`]s which runs as a single game. The game exits once the last
+/// state is popped.
+#[derive(Default)]
+pub struct StateStack {
+    states: Vec<Box<dyn GameState>>,
+    /// The scene of an empty stack, for the frame before the runtime exits.
+    empty_scene: Scene,
+}
+
+impl StateStack {
+    pub fn new(initial: Box<dyn GameState>) -> StateStack {
+        let mut stack = StateStack::default();
+        stack.apply(Transition::Push(initial));
+        stack
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn top(&self) -> Option<&dyn GameState> {
+        self.states.last().map(|state| state.as_ref())
+    }
+
+    fn top_mut(&mut self) -> Option<&mut Box<dyn GameState>> {
+        self.states.last_mut()
+    }
+
+    pub fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::None | Transition::Ignored => {}
+            Transition::Push(mut state) => {
+                if let Some(top) = self.states.last_mut() {
+                    top.on_leave();
+                }
+                state.on_enter();
+                self.states.push(state);
+            }
+            Transition::Pop => {
+                if let Some(mut top) = self.states.pop() {
+                    top.on_leave();
+                }
+                if let Some(top) = self.states.last_mut() {
+                    top.on_enter();
+                }
+            }
+            Transition::Replace(mut state) => {
+                if let Some(mut top) = self.states.pop() {
+                    top.on_leave();
+                }
+                state.on_enter();
+                self.states.push(state);
+            }
+        }
+    }
+
+    /// Index of the lowest state that is drawn.
+    fn first_visible(&self) -> usize {
+        self.states
+            .iter()
+            .rposition(|state| !state.is_transparent())
+            .unwrap_or(0)
+    }
+
+    /// Pass an event to the top state, returning whether it handled it, i.e. there was a
+    /// top state and it didn't return [`Transition::Ignored`].
+    fn with_top(&mut self, f: impl FnOnce(&mut dyn GameState) -> Transition) -> bool {
+        let Some(top) = self.states.last_mut() else {
+            return false;
+        };
+        let transition = f(top.as_mut());
+        let handled = !matches!(transition, Transition::Ignored);
+        self.apply(transition);
+        handled
+    }
+}
+
+impl GameHandler for StateStack {
+    fn start_game(&mut self) {
+        self.with_top(|state| state.start());
+    }
+
+    fn next(&mut self, dt: Duration) {
+        self.with_top(|state| state.next(dt));
+    }
+
+    fn next_profiled(&mut self, dt: Duration, profiler: &mut Profiler) {
+        self.with_top(|state| state.next_profiled(dt, profiler));
+    }
+
+    /// The top state's scene, or an empty scene once every state has been popped.
+    fn scene(&self) -> &Scene {
+        self.top().map_or(&self.empty_scene, |state| state.scene())
+    }
+
+    /// The scenes of every visible state, bottom first, drawn without being merged.
+    fn scenes(&self) -> Vec<&Scene> {
+        self.states[self.first_visible()..]
+            .iter()
+            .map(|state| state.scene())
+            .collect()
+    }
+
+    fn scene_mut(&mut self) -> Option<&mut Scene> {
+        self.top_mut().and_then(|state| state.scene_mut())
+    }
+
+    fn should_exit(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn debug_str(&self) -> Option<String> {
+        self.top().and_then(|state| state.debug_str())
+    }
+
+    fn camera(&self) -> CameraConfig {
+        self.top().map(|state| state.camera()).unwrap_or_default()
+    }
+
+    fn hud(&self, hud: &mut Hud) {
+        for state in &self.states[self.first_visible()..] {
+            state.hud(hud);
+        }
+    }
+
+    fn handle_key(&mut self, key: Key, pressed: bool) -> bool {
+        self.with_top(|state| state.handle_key(key, pressed))
+    }
+
+    fn handle_gamepad(&mut self, event: GamepadEvent) -> bool {
+        self.with_top(|state| state.handle_gamepad(event))
+    }
+
+    fn text_input_enabled(&self) -> bool {
+        self.top().is_some_and(|state| state.text_input_enabled())
+    }
+
+    fn handle_text(&mut self, input: TextInput) {
+        self.with_top(|state| state.handle_text(input));
+    }
+
+    fn time_scale(&self) -> f32 {
+        self.top().map_or(1.0, |state| state.time_scale())
+    }
+
+    fn on_profile(&mut self, profiler: &Profiler) {
+        if let Some(top) = self.top_mut() {
+            top.on_profile(profiler);
+        }
+    }
+
+    fn on_pause(&mut self) {
+        if let Some(top) = self.top_mut() {
+            top.on_pause();
+        }
+    }
+
+    fn on_resume(&mut self) {
+        if let Some(top) = self.top_mut() {
+            top.on_resume();
+        }
+    }
+
+    fn on_focus_changed(&mut self, focused: bool) {
+        if let Some(top) = self.top_mut() {
+            top.on_focus_changed(focused);
+        }
+    }
+
+    fn on_resize(&mut self, width: u32, height: u32) {
+        for state in &mut self.states {
+            state.on_resize(width, height);
+        }
+    }
+
+    fn on_exit(&mut self) {
+        for state in self.states.iter_mut().rev() {
+            state.on_exit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::gamepad::GamepadId;
+    use crate::scene::{Components, PositionComponent};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    struct TestState {
+        name: &'static str,
+        scene: Scene,
+        transparent: bool,
+        log: Log,
+    }
+
+    impl TestState {
+        fn boxed(name: &'static str, transparent: bool, log: &Log) -> Box<TestState> {
+            let mut scene = Scene::new();
+            scene.add_entity_from_components(Components {
+                position: Some(PositionComponent { x: 0.0, y: 0.0 }),
+                ..Components::new()
+            });
+            Box::new(TestState {
+                name,
+                scene,
+                transparent,
+                log: log.clone(),
+            })
+        }
+    }
+
+    impl GameState for TestState {
+        fn scene(&self) -> &Scene {
+            &self.scene
+        }
+
+        fn next(&mut self, _dt: Duration) -> Transition {
+            self.log.borrow_mut().push(format!("{} next", self.name));
+            Transition::None
+        }
+
+        fn handle_key(&mut self, key: Key, pressed: bool) -> Transition {
+            if !pressed {
+                return Transition::None;
+            }
+            match key {
+                Key::Escape => Transition::Pop,
+                Key::Space => Transition::Push(TestState::boxed("pause", true, &self.log)),
+                Key::Q => Transition::Replace(TestState::boxed("menu", false, &self.log)),
+                _ => Transition::Ignored,
+            }
+        }
+
+        fn is_transparent(&self) -> bool {
+            self.transparent
+        }
+
+        fn on_enter(&mut self) {
+            self.log.borrow_mut().push(format!("{} enter", self.name));
+        }
+
+        fn on_leave(&mut self) {
+            self.log.borrow_mut().push(format!("{} leave", self.name));
+        }
+
+        fn time_scale(&self) -> f32 {
+            if self.transparent {
+                0.5
+            } else {
+                1.0
+            }
+        }
+
+        fn on_pause(&mut self) {
+            self.log.borrow_mut().push(format!("{} pause", self.name));
+        }
+
+        fn on_resize(&mut self, _width: u32, _height: u32) {
+            self.log.borrow_mut().push(format!("{} resize", self.name));
+        }
+
+        fn on_exit(&mut self) {
+            self.log.borrow_mut().push(format!("{} exit", self.name));
+        }
+    }
+
+    fn take(log: &Log) -> Vec<String> {
+        log.borrow_mut().drain(..).collect()
+    }
+
+    #[test]
+    fn test_push_and_pop_transitions() {
+        let log = Log::default();
+        let mut stack = StateStack::new(TestState::boxed("game", false, &log));
+        assert_eq!(take(&log), vec!["game enter"]);
+
+        assert!(stack.handle_key(Key::Space, true));
+        assert_eq!(stack.len(), 2);
+        assert_eq!(take(&log), vec!["game leave", "pause enter"]);
+        assert!(!stack.handle_key(Key::Left, true));
+        assert!(!stack.handle_gamepad(GamepadEvent::Connected(GamepadId(0))));
+
+        stack.next(Duration::from_millis(16));
+        assert_eq!(take(&log), vec!["pause next"]);
+
+        stack.handle_key(Key::Escape, true);
+        assert_eq!(stack.len(), 1);
+        assert_eq!(take(&log), vec!["pause leave", "game enter"]);
+    }
+
+    #[test]
+    fn test_replace_transition() {
+        let log = Log::default();
+        let mut stack = StateStack::new(TestState::boxed("game", false, &log));
+        take(&log);
+
+        stack.handle_key(Key::Q, true);
+        assert_eq!(stack.len(), 1);
+        assert_eq!(take(&log), vec!["game leave", "menu enter"]);
+    }
+
+    #[test]
+    fn test_transparent_state_draws_over_state_beneath() {
+        let log = Log::default();
+        let mut stack = StateStack::new(TestState::boxed("game", false, &log));
+        assert_eq!(stack.scenes().len(), 1);
+
+        stack.handle_key(Key::Space, true);
+        let scenes = stack.scenes();
+        assert_eq!(scenes.len(), 2);
+        // Each state's scene is drawn as it is, so ids are only unique within a scene and
+        // `scene()` is the top one.
+        assert_eq!(scenes[0].entities()[0].id, scenes[1].entities()[0].id);
+        assert!(std::ptr::eq(scenes[1], stack.scene()));
+
+        stack.apply(Transition::Push(TestState::boxed("menu", false, &log)));
+        assert_eq!(stack.scenes().len(), 1);
+
+        stack.handle_key(Key::Escape, true);
+        assert_eq!(stack.scenes().len(), 2);
+        stack.handle_key(Key::Escape, true);
+        assert_eq!(stack.scenes().len(), 1);
+    }
+
+    #[test]
+    fn test_hooks_are_forwarded_and_empty_stack_exits() {
+        let log = Log::default();
+        let mut stack = StateStack::new(TestState::boxed("game", false, &log));
+        stack.handle_key(Key::Space, true);
+        take(&log);
+
+        stack.on_pause();
+        stack.next_profiled(Duration::ZERO, &mut Profiler::new());
+        stack.on_resize(80, 24);
+        stack.on_exit();
+        assert_eq!(
+            take(&log),
+            vec![
+                "pause pause",
+                "pause next",
+                "game resize",
+                "pause resize",
+                "pause exit",
+                "game exit"
+            ]
+        );
+        assert_eq!(stack.time_scale(), 0.5);
+
+        assert!(!stack.should_exit());
+        assert!(stack.handle_key(Key::Escape, true));
+        assert!(stack.handle_key(Key::Escape, true));
+        assert!(stack.should_exit());
+        assert!(!stack.handle_key(Key::Escape, true));
+        assert!(stack.scene().entities().is_empty());
+        assert!(stack.scenes().is_empty());
+    }
+}
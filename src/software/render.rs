@@ -0,0 +1,343 @@
+//! Draws a game into an in-memory RGBA image on the CPU.
+//!
+//! [`SoftwareRenderer`] builds the same instances and follows the same camera rules as the
+//! window renderer, then fills each quad pixel by pixel. It needs no GPU, so it works on
+//! headless machines and serves as a reference for the window's output. Like the window, it
+//! blends in linear colour and writes sRGB pixels.
+
+use crate::font;
+use crate::runtime::capture;
+use crate::runtime::hud::Hud;
+use crate::runtime::GameHandler;
+use crate::scene::{AtlasId, Entity};
+use crate::wgpu::render::{
+    CameraRig, CameraStrategy, CameraUniform, Instances, Projection, RenderMode, TextureSlot,
+};
+use cgmath::Vector3;
+use std::path::Path;
+use std::time::Duration;
+
+pub struct SoftwareRenderer {
+    width: u32,
+    height: u32,
+    /// Linear colour of each pixel, row by row from the top left.
+    pixels: Vec<[f32; 3]>,
+    frame: image::RgbaImage,
+    render_mode: RenderMode,
+    clear_color: Vector3<f32>,
+    atlases: Vec<image::RgbaImage>,
+    atlas_sizes: Vec<(u32, u32)>,
+    camera_rig: CameraRig,
+    instances: Instances,
+    hud: Hud,
+}
+
+impl SoftwareRenderer {
+    pub fn new(width: u32, height: u32, camera_strategy: CameraStrategy) -> SoftwareRenderer {
+        SoftwareRenderer {
+            width,
+            height,
+            pixels: vec![[0.0; 3]; (width * height) as usize],
+            frame: image::RgbaImage::new(width, height),
+            render_mode: RenderMode::default(),
+            clear_color: Vector3::new(1.0, 1.0, 1.0),
+            atlases: vec![],
+            atlas_sizes: vec![],
+            camera_rig: CameraRig::new(
+                camera_strategy,
+                Projection::default(),
+                (width as f32, height as f32),
+            ),
+            instances: Instances::default(),
+            hud: Hud::new(),
+        }
+    }
+
+    /// What the camera looks at, unless overridden by [`GameHandler::camera`].
+    pub fn set_camera_strategy(&mut self, camera_strategy: CameraStrategy) {
+        self.camera_rig.strategy = camera_strategy;
+    }
+
+    /// Draw entities as coloured blocks (the default) or as their ASCII characters.
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        self.render_mode = render_mode;
+    }
+
+    /// Use a perspective (the default) or orthographic camera.
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.camera_rig.camera.projection = projection;
+    }
+
+    /// Background colour behind the scene, white by default.
+    pub fn set_clear_color(&mut self, clear_color: Vector3<f32>) {
+        self.clear_color = clear_color;
+    }
+
+    /// Add a texture atlas for [`SpriteComponent`](crate::scene::SpriteComponent)s to
+    /// reference. Atlases are numbered in the order they are added.
+    pub fn add_atlas(&mut self, image: image::DynamicImage) -> AtlasId {
+        let image = image.to_rgba8();
+        self.atlas_sizes.push(image.dimensions());
+        self.atlases.push(image);
+        AtlasId(self.atlases.len() as u16 - 1)
+    }
+
+    /// Decode an encoded image, e.g. from `include_bytes!`, and add it as an atlas.
+    pub fn add_atlas_from_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<AtlasId> {
+        Ok(self.add_atlas(image::load_from_memory(bytes)?))
+    }
+
+    /// The last rendered frame.
+    pub fn frame(&self) -> &image::RgbaImage {
+        &self.frame
+    }
+
+    /// Save the last rendered frame as a PNG.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        capture::save_png(&self.frame, path)
+    }
+
+    /// Draw the game's scene, overlay and debug line, moving the camera on by `dt`.
+    pub fn render(&mut self, game: &dyn GameHandler, dt: Duration) -> &image::RgbaImage {
+        let scenes = game.scenes();
+        let entities: Vec<&Entity> = scenes.iter().flat_map(|scene| scene.entities()).collect();
+        self.instances
+            .rebuild(&entities, &self.atlas_sizes, self.render_mode);
+        self.camera_rig
+            .update(&entities, game.scene().entities(), game.camera(), dt);
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&self.camera_rig.camera);
+
+        let clear_color = self.clear_color.into();
+        self.pixels.fill(clear_color);
+        self.draw(camera_uniform.view_proj.into());
+
+        self.hud.clear();
+        game.hud(&mut self.hud);
+        if let Some(debug) = game.debug_str() {
+            self.hud.add_debug_lines(&debug, self.height as f32);
+        }
+        self.instances.rebuild_hud(&self.hud, self.height as f32);
+        camera_uniform.update_screen(self.width as f32, self.height as f32);
+        self.draw(camera_uniform.view_proj.into());
+
+        for (pixel, color) in self.frame.pixels_mut().zip(&self.pixels) {
+            *pixel = image::Rgba([
+                linear_to_srgb(color[0]),
+                linear_to_srgb(color[1]),
+                linear_to_srgb(color[2]),
+                255,
+            ]);
+        }
+        &self.frame
+    }
+
+    /// Fill every instance in order, blending each over what is already drawn.
+    fn draw(&mut self, view_proj: cgmath::Matrix4<f32>) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        for (raw, texture) in self.instances.raw.iter().zip(&self.instances.textures) {
+            let transform = view_proj * cgmath::Matrix4::from(raw.model);
+            let to_pixel = |x: f32, y: f32| {
+                let clip = transform * cgmath::Vector4::new(x, y, 0.0, 1.0);
+                if clip.w <= 0.0 {
+                    return None;
+                }
+                Some((
+                    (clip.x / clip.w + 1.0) / 2.0 * width,
+                    (1.0 - clip.y / clip.w) / 2.0 * height,
+                ))
+            };
+            // The camera looks straight at the scene, so each quad stays an axis-aligned
+            // rectangle on screen.
+            let (Some(bottom_left), Some(top_right)) = (to_pixel(0.0, 0.0), to_pixel(1.0, 1.0))
+            else {
+                continue;
+            };
+            let quad_width = top_right.0 - bottom_left.0;
+            let quad_height = bottom_left.1 - top_right.1;
+            if quad_width <= 0.0 || quad_height <= 0.0 {
+                continue;
+            }
+
+            // Pixels whose centres are inside the quad.
+            let first = |edge: f32, size: f32| (edge - 0.5).ceil().clamp(0.0, size) as u32;
+            let columns = first(bottom_left.0, width)..first(top_right.0, width);
+            let rows = first(top_right.1, height)..first(bottom_left.1, height);
+            for y in rows {
+                // Measured from the top of the quad, like the texture.
+                let v = (y as f32 + 0.5 - top_right.1) / quad_height;
+                for x in columns.clone() {
+                    let u = (x as f32 + 0.5 - bottom_left.0) / quad_width;
+                    let texel = self.sample(
+                        *texture,
+                        raw.uv[0] + (raw.uv[2] - raw.uv[0]) * u,
+                        raw.uv[1] + (raw.uv[3] - raw.uv[1]) * v,
+                    );
+                    let alpha = texel[3];
+                    if alpha <= 0.0 {
+                        continue;
+                    }
+                    let pixel = &mut self.pixels[(y * self.width + x) as usize];
+                    for channel in 0..3 {
+                        let source = texel[channel] * raw.color[channel];
+                        pixel[channel] = source * alpha + pixel[channel] * (1.0 - alpha);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The linear colour and alpha of the nearest texel to `(u, v)`.
+    fn sample(&self, texture: TextureSlot, u: f32, v: f32) -> [f32; 4] {
+        let texel = |size: u32, coordinate: f32| {
+            ((coordinate * size as f32).floor().max(0.0) as u32).min(size - 1)
+        };
+        match texture {
+            TextureSlot::White => [1.0; 4],
+            TextureSlot::Glyphs => {
+                let x = texel(font::ATLAS_WIDTH, u);
+                let y = texel(font::ATLAS_HEIGHT, v);
+                let alpha = if font::atlas_pixel(x, y) { 1.0 } else { 0.0 };
+                [1.0, 1.0, 1.0, alpha]
+            }
+            TextureSlot::Atlas(id) => {
+                let atlas = &self.atlases[id.0 as usize];
+                // An empty atlas has no texels, so its sprites draw nothing.
+                if atlas.width() == 0 || atlas.height() == 0 {
+                    return [0.0; 4];
+                }
+                let pixel = atlas.get_pixel(texel(atlas.width(), u), texel(atlas.height(), v));
+                [
+                    srgb_to_linear(pixel[0]),
+                    srgb_to_linear(pixel[1]),
+                    srgb_to_linear(pixel[2]),
+                    pixel[3] as f32 / 255.0,
+                ]
+            }
+        }
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::Key;
+    use crate::scene::{
+        Components, PositionComponent, RenderComponent, Scene, SizeComponent, SpriteComponent,
+    };
+
+    struct TestGame {
+        scene: Scene,
+        debug: Option<String>,
+    }
+
+    impl TestGame {
+        fn new(debug: Option<&str>) -> TestGame {
+            let mut scene = Scene::new();
+            let mut block = Components::new();
+            block.position = Some(PositionComponent { x: 0.0, y: 0.0 });
+            block.size = Some(SizeComponent { x: 2.0, y: 2.0 });
+            block.render = Some(RenderComponent {
+                ascii_character: '#',
+                rgb: Vector3::new(1.0, 0.0, 0.0),
+                layer: 0,
+            });
+            scene.add_entity_from_components(block);
+            TestGame {
+                scene,
+                debug: debug.map(str::to_string),
+            }
+        }
+    }
+
+    impl GameHandler for TestGame {
+        fn start_game(&mut self) {}
+        fn next(&mut self, _dt: Duration) {}
+        fn scene(&self) -> &Scene {
+            &self.scene
+        }
+        fn debug_str(&self) -> Option<String> {
+            self.debug.clone()
+        }
+        fn handle_key(&mut self, _key: Key, _pressed: bool) -> bool {
+            true
+        }
+    }
+
+    fn renderer() -> SoftwareRenderer {
+        let mut renderer = SoftwareRenderer::new(40, 40, CameraStrategy::AllEntities);
+        renderer.set_projection(Projection::Orthographic {
+            pixels_per_unit: 10.0,
+            zoom: 1.0,
+        });
+        renderer
+    }
+
+    #[test]
+    fn test_block_is_drawn_at_the_centre_of_the_view() {
+        let mut renderer = renderer();
+        let frame = renderer.render(&TestGame::new(None), Duration::ZERO);
+
+        // The 2x2 block fills the middle 20x20 pixels of the frame.
+        assert_eq!(frame.get_pixel(10, 10), &image::Rgba([255, 0, 0, 255]));
+        assert_eq!(frame.get_pixel(29, 29), &image::Rgba([255, 0, 0, 255]));
+        assert_eq!(frame.get_pixel(9, 10), &image::Rgba([255, 255, 255, 255]));
+        assert_eq!(frame.get_pixel(30, 29), &image::Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_glyphs_and_debug_line_are_drawn() {
+        let mut renderer = renderer();
+        renderer.set_render_mode(RenderMode::Glyphs);
+        renderer.set_clear_color(Vector3::new(0.0, 0.0, 1.0));
+        let frame = renderer.render(&TestGame::new(Some("|")), Duration::ZERO);
+
+        let count = |color: [u8; 3]| frame.pixels().filter(|pixel| pixel.0[..3] == color).count();
+        // Glyphs only cover part of their cell, and the debug line is black on white.
+        let red = count([255, 0, 0]);
+        assert!(red > 0 && red < 400);
+        assert!(count([0, 0, 0]) > 0);
+        assert!(count([255, 255, 255]) > 0);
+        assert!(count([0, 0, 255]) > 0);
+    }
+
+    #[test]
+    fn test_sprites_from_empty_atlases_draw_nothing() {
+        let mut renderer = renderer();
+        let atlas = renderer.add_atlas(image::DynamicImage::new_rgba8(0, 4));
+        let mut game = TestGame::new(None);
+        let block = game.scene.entities()[0].id;
+        game.scene
+            .get_entity_by_id_mut(block)
+            .unwrap()
+            .components
+            .sprite = Some(SpriteComponent {
+            atlas,
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        });
+        let frame = renderer.render(&game, Duration::ZERO);
+        assert!(frame.pixels().all(|pixel| pixel.0 == [255, 255, 255, 255]));
+    }
+}
@@ -0,0 +1,100 @@
+use crate::runtime::capture::FrameRecorder;
+use crate::runtime::profiler::Profiler;
+use crate::runtime::{game_time_scale, GameHandler};
+use crate::software::render::SoftwareRenderer;
+use crate::wgpu::render::CameraStrategy;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Runs a game for a fixed number of frames without a window, drawing each frame with a
+/// [`SoftwareRenderer`].
+pub struct SoftwareRuntime {
+    renderer: SoftwareRenderer,
+    frame_time: Duration,
+    output_dir: Option<PathBuf>,
+    recorder: Option<FrameRecorder>,
+    profiler: Profiler,
+}
+
+impl SoftwareRuntime {
+    pub fn new(width: u32, height: u32) -> SoftwareRuntime {
+        SoftwareRuntime {
+            renderer: SoftwareRenderer::new(width, height, CameraStrategy::default()),
+            frame_time: Duration::from_secs_f32(1.0 / 60.0),
+            output_dir: None,
+            recorder: None,
+            profiler: Profiler::new(),
+        }
+    }
+
+    /// The renderer, to set the render mode, projection, atlases and so on.
+    pub fn renderer(&mut self) -> &mut SoftwareRenderer {
+        &mut self.renderer
+    }
+
+    /// Step the game by `frame_time`, scaled by [`GameHandler::time_scale`], every frame.
+    /// Defaults to 60 frames per second.
+    pub fn set_frame_time(&mut self, frame_time: Duration) {
+        self.frame_time = frame_time;
+    }
+
+    /// Save every frame to `dir` as `frame_00000.png`, `frame_00001.png` and so on.
+    pub fn save_frames_to(&mut self, dir: impl Into<PathBuf>) {
+        self.output_dir = Some(dir.into());
+    }
+
+    /// Record every frame, e.g. as an animated GIF, finished when the run ends.
+    pub fn record_frames(&mut self, recorder: FrameRecorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Timings of the frames run so far, e.g. to start a trace before [`SoftwareRuntime::start`]
+    /// and save it afterwards. The frame time is always `frame_time`, but the scopes show how
+    /// long updating and rendering took.
+    pub fn profiler(&mut self) -> &mut Profiler {
+        &mut self.profiler
+    }
+
+    /// Start the game and run it for `frames` frames, or until
+    /// [`GameHandler::should_exit`], returning the last one.
+    pub fn start(
+        &mut self,
+        game: &mut dyn GameHandler,
+        camera_strategy: CameraStrategy,
+        frames: u32,
+    ) -> anyhow::Result<image::RgbaImage> {
+        self.renderer.set_camera_strategy(camera_strategy);
+        if let Some(dir) = &self.output_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        game.start_game();
+        for frame in 0..frames {
+            self.profiler.record_frame(self.frame_time);
+            let game_dt = self.frame_time.mul_f32(game_time_scale(game));
+            let update_start = Instant::now();
+            game.next_profiled(game_dt, &mut self.profiler);
+            self.profiler
+                .record("update", update_start, update_start.elapsed());
+            self.profiler
+                .time("render", || self.renderer.render(game, self.frame_time));
+            game.on_profile(&self.profiler);
+            if let Some(dir) = &self.output_dir {
+                self.renderer
+                    .save_png(dir.join(format!("frame_{frame:05}.png")))?;
+            }
+            if let Some(recorder) = &mut self.recorder {
+                recorder.push(self.renderer.frame().clone(), self.frame_time)?;
+            }
+            if game.should_exit() {
+                break;
+            }
+        }
+        game.on_exit();
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish()?;
+        }
+
+        Ok(self.renderer.frame().clone())
+    }
+}
@@ -0,0 +1,250 @@
+//! asciinema recordings of terminal sessions.
+//!
+//! A [`CastRecorder`] tees every frame the [`TerminalRenderer`](crate::terminal::render::TerminalRenderer)
+//! writes into an [asciicast v2](https://docs.asciinema.org/manual/asciicast/v2/) file,
+//! which `asciinema play` or the web player can replay. [`Cast`] loads a recording back so
+//! it can be replayed in a terminal with
+//! [`TerminalRenderer::play`](crate::terminal::render::TerminalRenderer::play).
+//!
+//! A cast is a JSON header line followed by one JSON array per frame:
+//!
+//! ```text
+//! {"version":2,"width":80,"height":24}
+//! [0.05,"o","\u001b[2J..."]
+//! ```
+
+use anyhow::{bail, Context, Result};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Output written to the terminal at a time since the recording started.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastEvent {
+    pub time: Duration,
+    pub data: String,
+}
+
+/// A loaded asciicast recording.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Cast {
+    pub width: u16,
+    pub height: u16,
+    pub events: Vec<CastEvent>,
+}
+
+impl Cast {
+    pub fn new(width: u16, height: u16) -> Cast {
+        Cast {
+            width,
+            height,
+            events: vec![],
+        }
+    }
+
+    /// Load a cast from a file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Cast> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read cast {}", path.display()))?;
+        Cast::parse(&contents)
+    }
+
+    /// Parse an asciicast v2 recording. Only output events are kept.
+    pub fn parse(contents: &str) -> Result<Cast> {
+        let mut lines = contents.lines().enumerate();
+        let header = match lines.next() {
+            Some((_, header)) => header,
+            None => bail!("Missing cast header"),
+        };
+        let header: Header = serde_json::from_str(header).context("Invalid cast header")?;
+        if header.version != 2 {
+            bail!("Unsupported cast version {}, expected 2", header.version);
+        }
+        let mut cast = Cast::new(header.width, header.height);
+
+        for (line_number, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let event = parse_event(line)
+                .with_context(|| format!("Invalid cast line {}", line_number + 1))?;
+            if let Some(event) = event {
+                cast.events.push(event);
+            }
+        }
+        Ok(cast)
+    }
+
+    /// How long the recording lasts.
+    pub fn duration(&self) -> Duration {
+        self.events
+            .last()
+            .map_or(Duration::ZERO, |event| event.time)
+    }
+}
+
+impl fmt::Display for Cast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", header(self.width, self.height))?;
+        for event in &self.events {
+            writeln!(f, "{}", event_line(event.time, &event.data))?;
+        }
+        Ok(())
+    }
+}
+
+/// Streams frames into a cast file as they are drawn, so the recording survives a crash.
+pub struct CastRecorder {
+    writer: BufWriter<fs::File>,
+    start: Instant,
+}
+
+impl CastRecorder {
+    /// Create the cast file at `path` for a terminal `width` by `height` characters.
+    pub fn create(path: impl AsRef<Path>, width: u16, height: u16) -> Result<CastRecorder> {
+        let path = path.as_ref();
+        let file = fs::File::create(path)
+            .with_context(|| format!("Unable to create cast {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", header(width, height))?;
+        Ok(CastRecorder {
+            writer,
+            start: Instant::now(),
+        })
+    }
+
+    /// Record `data` as written to the terminal now.
+    pub fn record(&mut self, data: &str) -> Result<()> {
+        writeln!(self.writer, "{}", event_line(self.start.elapsed(), data))?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The header line of a cast. Sizes may be written as `80.0` as well as `80`.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    #[serde(deserialize_with = "size")]
+    width: u16,
+    #[serde(deserialize_with = "size")]
+    height: u16,
+}
+
+fn size<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<u16, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if value.fract() == 0.0 && (0.0..=f64::from(u16::MAX)).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(D::Error::custom(format!("invalid terminal size {}", value)))
+    }
+}
+
+fn header(width: u16, height: u16) -> String {
+    let header = Header {
+        version: 2,
+        width,
+        height,
+    };
+    serde_json::to_string(&header).expect("cast headers serialize")
+}
+
+/// `[time, "o", data]`, with the time rounded to microseconds like asciinema writes it.
+fn event_line(time: Duration, data: &str) -> String {
+    let time = time.as_micros() as f64 / 1e6;
+    serde_json::to_string(&(time, "o", data)).expect("cast events serialize")
+}
+
+/// Parse `[time, "code", "data"]`, returning `None` for events other than output.
+fn parse_event(line: &str) -> Result<Option<CastEvent>> {
+    let (time, code, data): (f64, String, String) = serde_json::from_str(line)?;
+    if code != "o" {
+        return Ok(None);
+    }
+    let time = Duration::try_from_secs_f64(time.max(0.0))
+        .with_context(|| format!("Invalid time {}", time))?;
+    Ok(Some(CastEvent { time, data }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cast_round_trip() {
+        let mut cast = Cast::new(80, 24);
+        cast.events.push(CastEvent {
+            time: Duration::from_millis(50),
+            data: "\u{1b}[2J\u{1b}[1;1H..@..\r\n\"quoted\" \\ tab\t é 🐍".to_string(),
+        });
+        cast.events.push(CastEvent {
+            time: Duration::from_millis(100),
+            data: String::new(),
+        });
+
+        let text = cast.to_string();
+        assert!(text.starts_with("{\"version\":2,\"width\":80,\"height\":24}\n"));
+        assert!(text.contains("[0.05,\"o\",\"\\u001b[2J"));
+        assert_eq!(Cast::parse(&text).unwrap(), cast);
+        assert_eq!(cast.duration(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_parse_asciinema_output() {
+        let text = concat!(
+            "{\"version\": 2, \"width\": 10, \"height\": 5, \"timestamp\": 1504467315}\n",
+            "[0.248848, \"o\", \"\\u001b[1;31mhi\\ud83d\\udc0d\"]\n",
+            "[1.001376, \"i\", \"q\"]\n",
+        );
+        let cast = Cast::parse(text).unwrap();
+        assert_eq!((cast.width, cast.height), (10, 5));
+        assert_eq!(cast.events.len(), 1);
+        assert_eq!(cast.events[0].data, "\u{1b}[1;31mhi🐍");
+
+        assert!(Cast::parse("{\"version\": 1, \"width\": 10, \"height\": 5}").is_err());
+        assert!(Cast::parse(&format!("{}\n[0.1, \"o\"]", header(1, 1))).is_err());
+    }
+
+    #[test]
+    fn test_header_sizes_are_read_as_numbers() {
+        let cast = Cast::parse(concat!(
+            "{\"version\": 2, \"env\": {\"width\": 3}, \"width\": 80.0, \"height\": 24}\n",
+            "[0.5, \"o\", \"\\ud83d\\udc0d\"]\n",
+        ))
+        .unwrap();
+        assert_eq!((cast.width, cast.height), (80, 24));
+        assert_eq!(cast.events[0].data, "🐍");
+
+        for header in [
+            "{\"version\": 2, \"width\": 80.5, \"height\": 24}",
+            "{\"version\": 2, \"width\": -1, \"height\": 24}",
+            "{\"version\": 2, \"width\": 70000, \"height\": 24}",
+            "{\"version\": 2, \"height\": 24}",
+        ] {
+            assert!(Cast::parse(header).is_err(), "{header}");
+        }
+    }
+
+    #[test]
+    fn test_unpaired_surrogates_are_rejected() {
+        for data in [
+            r#""\ud83d\u0041""#,
+            r#""\ud83dA""#,
+            r#""\ud83d""#,
+            r#""\udc0d""#,
+        ] {
+            let text = format!("{}\n[0.1, \"o\", {}]", header(1, 1), data);
+            assert!(Cast::parse(&text).is_err(), "{data}");
+        }
+    }
+}
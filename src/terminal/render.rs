@@ -1,12 +1,17 @@
 //! View, cursor and renderer.
 
+use crate::runtime::debug_draw::DebugBox;
+use crate::terminal::cast::{Cast, CastRecorder};
 use crate::{
-    scene::{Entity, PositionComponent},
+    runtime::camera::CameraTargeting,
+    scene::{sort_by_layer, ComponentType, Entity, EntityId, Scene},
     terminal::render::cursor::CursorStrategy,
 };
 use std::{
     io::{Stdout, Write},
     iter::zip,
+    thread,
+    time::Duration,
 };
 #[cfg(not(target_arch = "wasm32"))]
 use termion::raw::RawTerminal;
@@ -28,27 +33,85 @@ pub struct View {
     pub view_cursor: ViewCoordinate,
     pub renderer: Box<dyn Renderer>,
     pub cursor_strategy: Box<dyn CursorStrategy>,
+    pub camera_targeting: CameraTargeting,
+    /// The rows drawn by the last call to [`View::next`], top first, followed by a blank
+    /// row and the debug line, as laid out in the terminal.
+    pub(crate) last_frame: Vec<String>,
+    /// Collision debug boxes to draw over the scene, if any.
+    pub(crate) debug_boxes: Vec<DebugBox>,
+    /// Lines drawn over the top-left of the scene, e.g. the inspector.
+    pub(crate) panel: Vec<String>,
 }
 
 impl View {
+    /// A view at the origin, drawn with `renderer` and moved by `cursor_strategy`.
+    pub fn new(renderer: Box<dyn Renderer>, cursor_strategy: Box<dyn CursorStrategy>) -> View {
+        View {
+            view_cursor: ViewCoordinate { x: 0, y: 0 },
+            renderer,
+            cursor_strategy,
+            camera_targeting: CameraTargeting::new(),
+            last_frame: vec![],
+            debug_boxes: vec![],
+            panel: vec![],
+        }
+    }
+
+    /// Render the entities, moving the view by the rules on [`CameraTargeting`].
     pub fn next(&mut self, entities: Vec<&Entity>, debug_string: Option<String>) -> String {
+        self.next_following(entities, debug_string, None)
+    }
+
+    /// Render the entities, moving the view to follow `follow` (or, if it is missing, the
+    /// rules on [`CameraTargeting`]).
+    pub fn next_following(
+        &mut self,
+        entities: Vec<&Entity>,
+        debug_string: Option<String>,
+        follow: Option<EntityId>,
+    ) -> String {
+        self.move_view(follow, entities.iter().copied());
+        self.draw(entities, debug_string)
+    }
+
+    /// Render the entities of several scenes, bottom first, following an entity of the last
+    /// one, whose ids are the ones the game knows.
+    pub(crate) fn next_scenes(
+        &mut self,
+        scenes: &[&Scene],
+        debug_string: Option<String>,
+        follow: Option<EntityId>,
+    ) -> String {
+        if let Some(top) = scenes.last() {
+            self.move_view(follow, top.entities());
+        }
+        let entities = scenes
+            .iter()
+            .flat_map(|scene| scene.get_entities_with_component(ComponentType::Render))
+            .collect();
+        self.draw(entities, debug_string)
+    }
+
+    fn move_view<'a>(
+        &mut self,
+        follow: Option<EntityId>,
+        entities: impl IntoIterator<Item = &'a Entity> + Clone,
+    ) {
         let renderer = self.renderer.as_mut();
         let strategy = self.cursor_strategy.as_mut();
-        let maybe_trackable_entity = entities
-            .iter()
-            .find(|entity| entity.components.camera_follow.is_some());
-        if let Some(entity_to_track) = maybe_trackable_entity {
-            let position = entity_to_track.components.position;
-            let pos = position
-                .as_ref()
-                .unwrap_or(&PositionComponent { x: 0.0, y: 0.0 });
+        if let Some(target) = self.camera_targeting.target(follow, entities) {
             let coord = ViewCoordinate {
-                x: pos.x as i16,
-                y: pos.y as i16,
+                x: target.position.0 as i16,
+                y: target.position.1 as i16,
             };
             strategy.update(&mut self.view_cursor, &*renderer, &coord);
         }
+    }
 
+    fn draw(&mut self, mut entities: Vec<&Entity>, debug_string: Option<String>) -> String {
+        let renderer = self.renderer.as_mut();
+        // Later entities overwrite earlier ones, so draw the highest layers last.
+        sort_by_layer(&mut entities);
         let mut level_strings: Vec<String> = vec![];
 
         // TODO would it be much faster to just run through the entities and then render them at the relevant
@@ -90,12 +153,190 @@ impl View {
             level_strings.push(level);
         }
 
+        draw_panel(&mut level_strings, &self.panel);
+        let mut coloured_strings = None;
+        if !self.debug_boxes.is_empty() {
+            let overlay = DebugOverlay::new(
+                &level_strings,
+                &self.debug_boxes,
+                &self.view_cursor,
+                renderer.screen_height(),
+            );
+            level_strings = overlay.plain();
+            coloured_strings = Some(overlay.coloured());
+        }
+
+        self.last_frame.clone_from(&level_strings);
+        self.last_frame.push(String::new());
+        self.last_frame
+            .push(debug_string.clone().unwrap_or_default());
+
         let h: u16 = renderer.screen_height();
-        let view = renderer.player_view(level_strings);
+        let view = renderer.player_view(coloured_strings.unwrap_or(level_strings));
         renderer.render(debug_string, view, h)
     }
 }
 
+/// Draw `panel` over the start of the rows, padding its lines to the same width so it
+/// covers the scene beneath.
+fn draw_panel(rows: &mut [String], panel: &[String]) {
+    let width = panel
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (row, line) in rows.iter_mut().zip(panel) {
+        let row_width = row.chars().count();
+        let covered: String = line
+            .chars()
+            .chain(std::iter::repeat(' '))
+            .take(width.min(row_width))
+            .collect();
+        let rest: String = row.chars().skip(width).collect();
+        *row = covered + &rest;
+    }
+}
+
+/// A character of the collision debug overlay.
+#[derive(Clone, Copy)]
+struct DebugCell {
+    c: char,
+    colliding: bool,
+}
+
+/// The collision debug overlay drawn over the rows of the view. Box outlines, velocity
+/// arrows and ids only cover empty cells, so entities stay visible; entities inside a
+/// colliding box are highlighted.
+struct DebugOverlay {
+    rows: Vec<Vec<char>>,
+    cells: Vec<Vec<Option<DebugCell>>>,
+}
+
+impl DebugOverlay {
+    fn new(
+        levels: &[String],
+        boxes: &[DebugBox],
+        cursor: &ViewCoordinate,
+        screen_height: u16,
+    ) -> DebugOverlay {
+        let rows: Vec<Vec<char>> = levels.iter().map(|row| row.chars().collect()).collect();
+        let cells = rows.iter().map(|row| vec![None; row.len()]).collect();
+        let mut overlay = DebugOverlay { rows, cells };
+        // Row `r` shows world row `screen_height + cursor.y - r`.
+        let to_row = |y: i32| screen_height as i32 + cursor.y as i32 - y;
+        let to_column = |x: i32| x - cursor.x as i32;
+
+        for debug_box in boxes {
+            let left = debug_box.x.start.floor() as i32;
+            let right = debug_box.x.end.ceil() as i32 - 1;
+            let bottom = debug_box.y.start.floor() as i32;
+            let top = debug_box.y.end.ceil() as i32 - 1;
+            let colliding = debug_box.colliding;
+            for y in bottom..=top {
+                for x in left..=right {
+                    let on_x_edge = x == left || x == right;
+                    let on_y_edge = y == bottom || y == top;
+                    let c = match (on_x_edge, on_y_edge) {
+                        (true, true) => '+',
+                        (false, true) => '-',
+                        (true, false) => '|',
+                        (false, false) => ' ',
+                    };
+                    overlay.mark(to_row(y), to_column(x), c, colliding);
+                }
+            }
+
+            let (velocity_x, velocity_y) = debug_box.velocity;
+            if velocity_x != 0.0 || velocity_y != 0.0 {
+                let x = match velocity_x.partial_cmp(&0.0) {
+                    Some(std::cmp::Ordering::Greater) => right + 1,
+                    Some(std::cmp::Ordering::Less) => left - 1,
+                    _ => debug_box.centre.0.floor() as i32,
+                };
+                let y = match velocity_y.partial_cmp(&0.0) {
+                    Some(std::cmp::Ordering::Greater) => top + 1,
+                    Some(std::cmp::Ordering::Less) => bottom - 1,
+                    _ => debug_box.centre.1.floor() as i32,
+                };
+                let arrow = match (velocity_x > 0.0, velocity_x < 0.0, velocity_y > 0.0) {
+                    (true, _, _) if velocity_y == 0.0 => '>',
+                    (_, true, _) if velocity_y == 0.0 => '<',
+                    (false, false, true) => '^',
+                    (false, false, false) => 'v',
+                    (right, _, up) if right == up => '/',
+                    _ => '\\',
+                };
+                overlay.mark(to_row(y), to_column(x), arrow, colliding);
+            }
+
+            for (i, digit) in debug_box.id.0.to_string().chars().enumerate() {
+                overlay.mark(
+                    to_row(top + 1),
+                    to_column(left + i as i32),
+                    digit,
+                    colliding,
+                );
+            }
+        }
+        overlay
+    }
+
+    /// Draw `c` at a cell if it is on screen. Empty cells take the character; occupied cells
+    /// keep theirs but are highlighted if `colliding`.
+    fn mark(&mut self, row: i32, column: i32, c: char, colliding: bool) {
+        let (Ok(row), Ok(column)) = (usize::try_from(row), usize::try_from(column)) else {
+            return;
+        };
+        let Some(base) = self.rows.get(row).and_then(|r| r.get(column)) else {
+            return;
+        };
+        let cell = &mut self.cells[row][column];
+        if *base != '.' {
+            if colliding {
+                *cell = Some(DebugCell {
+                    c: *base,
+                    colliding,
+                });
+            }
+        } else if c != ' ' && cell.is_none_or(|cell| !cell.colliding || colliding) {
+            *cell = Some(DebugCell { c, colliding });
+        }
+    }
+
+    fn plain(&self) -> Vec<String> {
+        zip(&self.rows, &self.cells)
+            .map(|(row, cells)| {
+                zip(row, cells)
+                    .map(|(base, cell)| cell.map_or(*base, |cell| cell.c))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The rows with the overlay in yellow, or red where there is a collision.
+    fn coloured(&self) -> Vec<String> {
+        let yellow = termion::color::Fg(termion::color::Yellow).to_string();
+        let red = termion::color::Fg(termion::color::Red).to_string();
+        let reset = termion::color::Fg(termion::color::Reset).to_string();
+        zip(&self.rows, &self.cells)
+            .map(|(row, cells)| {
+                let mut line = String::new();
+                for (base, cell) in zip(row, cells) {
+                    match cell {
+                        Some(cell) => {
+                            line.push_str(if cell.colliding { &red } else { &yellow });
+                            line.push(cell.c);
+                            line.push_str(&reset);
+                        }
+                        None => line.push(*base),
+                    }
+                }
+                line
+            })
+            .collect()
+    }
+}
+
 /// Player view cursor and strategies.
 pub mod cursor {
     use crate::terminal::render::{Renderer, ViewCoordinate};
@@ -145,8 +386,7 @@ pub mod cursor {
             if abs_diff > 1 && abs_diff < (renderer.screen_height() - 2_u16) {
                 return;
             }
-            cursor.y =
-                (y as i16 + self.offset as i16 - renderer.screen_height() as i16).max(0) as i16;
+            cursor.y = (y + self.offset as i16 - renderer.screen_height() as i16).max(0);
         }
     }
 
@@ -173,8 +413,7 @@ pub mod cursor {
             if abs_diff > 1 && abs_diff < (renderer.screen_width() - 2_u16) {
                 return;
             }
-            cursor.x =
-                (x as i16 + self.offset as i16 - renderer.screen_width() as i16).max(0) as i16;
+            cursor.x = (x + self.offset as i16 - renderer.screen_width() as i16).max(0);
         }
     }
 
@@ -204,8 +443,7 @@ pub mod cursor {
             let y = coords.y;
             let y_abs_diff = y.abs_diff(cursor.y);
             if !(y_abs_diff > 1 && y_abs_diff < (renderer.screen_height() - 2_u16)) {
-                cursor.y = (y as i16 + self.y_offset as i16 - renderer.screen_height() as i16)
-                    .max(0) as i16;
+                cursor.y = (y + self.y_offset as i16 - renderer.screen_height() as i16).max(0);
             }
 
             // X axis logic: make the camera follow the player more smoothly,
@@ -213,7 +451,7 @@ pub mod cursor {
             let x = coords.x;
             let screen_w = renderer.screen_width();
             let left_margin = self.x_offset as i16;
-            let right_margin = (screen_w as i16).saturating_sub(self.x_offset as i16 + 1) as i16;
+            let right_margin = (screen_w as i16).saturating_sub(self.x_offset as i16 + 1);
 
             // If player is left of the left margin, move view left.
             if x < cursor.x + left_margin {
@@ -237,6 +475,10 @@ pub trait Renderer {
     fn screen_width(&self) -> u16;
     fn player_view(&mut self, levels: Vec<String>) -> String;
     fn render(&mut self, debug_string: Option<String>, view: String, h: u16) -> String;
+
+    /// Also write every frame to an asciicast recording. Renderers which don't draw to a
+    /// terminal ignore it.
+    fn record_cast(&mut self, _cast: CastRecorder) {}
 }
 
 /// A renderer for the terminal.
@@ -244,6 +486,7 @@ pub trait Renderer {
 pub struct TerminalRenderer {
     stdout: RawTerminal<Stdout>,
     screen_dimensions: ScreenDimensions,
+    cast: Option<CastRecorder>,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -255,28 +498,50 @@ impl TerminalRenderer {
         TerminalRenderer {
             stdout,
             screen_dimensions,
+            cast: None,
         }
     }
 
     fn stdout(&mut self) -> &mut RawTerminal<Stdout> {
         &mut self.stdout
     }
+
+    /// Replay a recording, waiting between frames as they were recorded. `speed` multiplies
+    /// the playback rate.
+    pub fn play(&mut self, cast: &Cast, speed: f32) -> anyhow::Result<()> {
+        let mut elapsed = Duration::ZERO;
+        for event in &cast.events {
+            if event.time > elapsed {
+                thread::sleep((event.time - elapsed).div_f32(speed.max(f32::EPSILON)));
+                elapsed = event.time;
+            }
+            write!(self.stdout(), "{}", event.data)?;
+            self.stdout().lock().flush()?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 impl Renderer for TerminalRenderer {
     fn render(&mut self, debug_string: Option<String>, view: String, h: u16) -> String {
-        write!(
-            self.stdout(),
+        let frame = format!(
             "{}{}{}{}{}",
             termion::clear::All,
             termion::cursor::Goto(1, 1),
             view,
             termion::cursor::Goto(1, h + 2),
             debug_string.unwrap_or("".to_string())
-        )
-        .unwrap();
+        );
+        write!(self.stdout(), "{}", frame).unwrap();
         self.stdout().lock().flush().unwrap();
+        if let Some(cast) = &mut self.cast {
+            if let Err(e) = cast.record(&frame) {
+                // Stop recording rather than fail every frame.
+                self.cast = None;
+                log::error!("Unable to record cast: {:#}", e);
+            }
+        }
         // TODO unused return value as we flush to the stdout in terminal renderer
         // different use case of terminal vs web, but align to the same trait - worth
         // looking at
@@ -305,6 +570,19 @@ impl Renderer for TerminalRenderer {
     fn screen_height(&self) -> u16 {
         self.screen_dimensions.y
     }
+
+    fn record_cast(&mut self, cast: CastRecorder) {
+        self.cast = Some(cast);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for TerminalRenderer {
+    fn drop(&mut self) {
+        if let Some(cast) = &mut self.cast {
+            let _ = cast.flush();
+        }
+    }
 }
 
 /// Utility function to build a string of a given character and length.
@@ -320,4 +598,26 @@ mod test {
         let input = build_string('@', 3);
         assert_eq!(input, "@@@");
     }
+
+    #[test]
+    fn test_debug_overlay() {
+        let mut levels = vec!["......".to_string(); 5];
+        levels[4] = ".#....".to_string();
+        let debug_box = DebugBox {
+            id: EntityId(7),
+            x: 1.0..4.0,
+            y: 1.0..3.0,
+            centre: (1.5, 1.5),
+            velocity: (2.0, 0.0),
+            colliding: true,
+        };
+        let overlay = DebugOverlay::new(&levels, &[debug_box], &ViewCoordinate { x: 0, y: 0 }, 5);
+
+        assert_eq!(
+            overlay.plain(),
+            vec!["......", "......", ".7....", ".+-+..", ".#-+>."]
+        );
+        let red = termion::color::Fg(termion::color::Red).to_string();
+        assert!(overlay.coloured()[4].starts_with(&format!(".{red}#")));
+    }
 }
@@ -1,16 +1,67 @@
+use crate::runtime::camera::CameraStrategy;
+use crate::runtime::capture::{self, Capture, FrameRecorder};
+use crate::runtime::debug_draw::debug_boxes;
+use crate::runtime::gamepad::{GamepadBackend, GamepadInput};
+use crate::runtime::inspector::Inspector;
+use crate::runtime::profiler::Profiler;
+use crate::runtime::recording::{InputEvent, InputRecorder};
 use crate::runtime::GameHandler;
 use crate::runtime::Key;
-use crate::scene::ComponentType;
+use crate::runtime::RuntimeState;
+use crate::runtime::TextInput;
+use crate::terminal::cast::CastRecorder;
 use crate::terminal::render::View;
 use crate::terminal::render::{
-    cursor::FollowPlayerXYCursorStrategy, ScreenDimensions, TerminalRenderer, ViewCoordinate,
+    cursor::FollowPlayerXYCursorStrategy, ScreenDimensions, TerminalRenderer,
 };
 use std::io::Stdout;
+use std::path::{Path, PathBuf};
 use std::thread;
-use std::time::{self, Duration, Instant};
+use std::time::{Duration, Instant};
 use termion::raw::RawTerminal;
 
-const REFRESH_RATE: u64 = 50;
+/// Frames per second unless changed with [`TerminalRuntime::set_target_fps`].
+pub const DEFAULT_TARGET_FPS: u32 = 60;
+/// How long after the last key press held keys count as released.
+const KEY_RELEASE_DELAY: Duration = Duration::from_millis(50);
+
+/// Keys pressed in the terminal and not yet released. Terminals don't report key releases,
+/// so held keys count as released once no key has arrived for [`KEY_RELEASE_DELAY`], and
+/// each is released once however often it repeated.
+#[derive(Debug)]
+struct HeldKeys {
+    keys: Vec<Key>,
+    last_input_time: Instant,
+}
+
+impl HeldKeys {
+    fn new(now: Instant) -> HeldKeys {
+        HeldKeys {
+            keys: vec![],
+            last_input_time: now,
+        }
+    }
+
+    /// Note that a key arrived at `now`, keeping the held keys down.
+    fn input(&mut self, now: Instant) {
+        self.last_input_time = now;
+    }
+
+    fn press(&mut self, key: Key) {
+        if !self.keys.contains(&key) {
+            self.keys.push(key);
+        }
+    }
+
+    /// The held keys to release at `now`.
+    fn release(&mut self, now: Instant) -> Vec<Key> {
+        if now.saturating_duration_since(self.last_input_time) > KEY_RELEASE_DELAY {
+            std::mem::take(&mut self.keys)
+        } else {
+            vec![]
+        }
+    }
+}
 
 impl TryFrom<termion::event::Key> for Key {
     type Error = &'static str;
@@ -29,6 +80,31 @@ impl TryFrom<termion::event::Key> for Key {
     }
 }
 
+impl TryFrom<termion::event::Key> for TextInput {
+    type Error = &'static str;
+
+    fn try_from(key: termion::event::Key) -> Result<TextInput, &'static str> {
+        match key {
+            termion::event::Key::Char('\n') => Ok(TextInput::Enter),
+            termion::event::Key::Backspace => Ok(TextInput::Backspace),
+            termion::event::Key::Char(c) if !c.is_control() => Ok(TextInput::Char(c)),
+            _ => Err("Not text input"),
+        }
+    }
+}
+
+/// The terminal starts the game when Space is pressed rather than passing the key on, so
+/// treat Space from the gamepad key map the same way.
+fn start_on_space(event: InputEvent) -> Option<InputEvent> {
+    match event {
+        InputEvent::Key {
+            key: Key::Space,
+            pressed,
+        } => pressed.then_some(InputEvent::StartGame),
+        event => Some(event),
+    }
+}
+
 /// Initialize terminal IO.
 pub fn initialize_terminal_io() -> (
     RawTerminal<Stdout>,
@@ -48,73 +124,303 @@ pub struct TerminalRuntime {
     pub stdin: termion::input::Keys<termion::AsyncReader>,
     pub display: View,
     pub(crate) last_frame_time: Instant,
-    pub(crate) player_control_key: Option<Key>,
+    held_keys: HeldKeys,
+    frame_time: Duration,
+    pub(crate) recorder: Option<InputRecorder>,
+    pub(crate) gamepad: Option<GamepadInput>,
+    pub(crate) runtime_state: RuntimeState,
+    terminal_size: Option<(u16, u16)>,
+    capture: Capture,
+    debug_draw: bool,
+    inspector: Inspector,
+    profiler: Profiler,
+    trace_path: Option<PathBuf>,
 }
 
 impl TerminalRuntime {
     pub fn new(width: u16, height: u16) -> TerminalRuntime {
         let (stdout, stdin) = initialize_terminal_io();
 
-        let view = View {
-            view_cursor: ViewCoordinate { x: 0, y: 0 },
-            renderer: Box::new(TerminalRenderer::new(
+        let view = View::new(
+            Box::new(TerminalRenderer::new(
                 stdout,
                 ScreenDimensions {
                     x: width,
                     y: height,
                 },
             )),
-            cursor_strategy: Box::new(FollowPlayerXYCursorStrategy::new()),
-        };
+            Box::new(FollowPlayerXYCursorStrategy::new()),
+        );
 
         TerminalRuntime {
             stdin,
             last_frame_time: Instant::now(),
             display: view,
-            player_control_key: None,
+            held_keys: HeldKeys::new(Instant::now()),
+            frame_time: Duration::from_secs(1) / DEFAULT_TARGET_FPS,
+            recorder: None,
+            gamepad: None,
+            runtime_state: RuntimeState::default(),
+            terminal_size: termion::terminal_size().ok(),
+            capture: Capture::default(),
+            debug_draw: false,
+            inspector: Inspector::default(),
+            profiler: Profiler::new(),
+            trace_path: None,
         }
     }
 
-    /// Start the game loop listening for player input and rendering the game.
-    pub fn start(&mut self, game: &mut dyn GameHandler) {
-        loop {
-            let input = self.stdin.next();
+    /// Update and draw the game `fps` times a second, sleeping for whatever is left of each
+    /// frame. Defaults to [`DEFAULT_TARGET_FPS`].
+    pub fn set_target_fps(&mut self, fps: u32) {
+        self.frame_time = Duration::from_secs(1) / fps.max(1);
+    }
+
+    /// Poll `backend` for gamepad events every frame.
+    pub fn set_gamepad_backend(&mut self, backend: Box<dyn GamepadBackend>) {
+        self.gamepad = Some(GamepadInput::new(backend));
+    }
+
+    /// Record every input event and frame time to `path`, saved when the game exits.
+    pub fn record_input(&mut self, path: impl Into<PathBuf>) {
+        self.recorder = Some(InputRecorder::new(path));
+    }
+
+    /// Record everything drawn to the terminal as an asciicast file at `path`, playable
+    /// with `asciinema play` or [`TerminalRenderer::play`].
+    pub fn record_cast(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let (width, height) = self.terminal_size.unwrap_or((
+            self.display.renderer.screen_width(),
+            self.display.renderer.screen_height() + 2,
+        ));
+        let cast = CastRecorder::create(path, width, height)?;
+        self.display.renderer.record_cast(cast);
+        Ok(())
+    }
+
+    /// Save a PNG of the screen into `dir` when F12 is pressed.
+    pub fn set_screenshot_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.capture.set_screenshot_dir(dir.into());
+    }
 
-            if let Some(Ok(key)) = input {
-                if let Ok(key) = Key::try_from(key) {
+    /// Record every frame, saved when the game exits.
+    pub fn record_frames(&mut self, recorder: FrameRecorder) {
+        self.capture.recorder = Some(recorder);
+    }
+
+    /// Trace every frame, written to `path` as Chrome trace JSON when the game exits.
+    pub fn save_chrome_trace(&mut self, path: impl Into<PathBuf>) {
+        self.profiler.start_trace();
+        self.trace_path = Some(path.into());
+    }
+
+    /// Scale `dt` for slow motion or fast forward, between 1/16 and 16. F7 and F8 halve and
+    /// double it. A value that isn't finite is logged and ignored.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        if let Err(e) = self.runtime_state.set_time_scale(time_scale) {
+            log::error!("{:#}", e);
+        }
+    }
+
+    /// Draw collision boxes, velocities and entity ids over the scene. Toggled with F3.
+    pub fn set_debug_draw(&mut self, enabled: bool) {
+        self.debug_draw = enabled;
+    }
+
+    fn dispatch(&mut self, game: &mut dyn GameHandler, event: InputEvent) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(event);
+        }
+        event.apply(game);
+    }
+
+    /// Handle a key press, returning `false` to quit.
+    fn handle_key(&mut self, game: &mut dyn GameHandler, key: termion::event::Key) -> bool {
+        match key {
+            termion::event::Key::F(12) => self.capture.request_screenshot(),
+            termion::event::Key::F(3) => self.debug_draw = !self.debug_draw,
+            termion::event::Key::F(n @ 5..=8) => {
+                self.runtime_state.handle_function_key(game, n);
+            }
+            termion::event::Key::F(1) => self.inspector.toggle(),
+            key if self.inspector.is_open() => match key {
+                termion::event::Key::Up => self.inspector.select_next(game.scene(), -1),
+                termion::event::Key::Down => self.inspector.select_next(game.scene(), 1),
+                key => {
+                    if let Ok(text_input) = TextInput::try_from(key) {
+                        self.inspector
+                            .handle_text(text_input, game, &mut self.runtime_state);
+                    }
+                }
+            },
+            key => {
+                let text_input = TextInput::try_from(key)
+                    .ok()
+                    .filter(|_| game.text_input_enabled());
+                if let Some(text_input) = text_input {
+                    self.dispatch(game, InputEvent::Text(text_input));
+                } else if let Ok(key) = Key::try_from(key) {
                     match key {
-                        Key::Q => break,
-                        key if key != Key::Space => {
-                            game.handle_key(key.into(), true);
-                        }
-                        Key::Space => {
-                            game.start_game();
-                        }
-                        _ => {
-                            self.player_control_key = None;
+                        Key::Q => return false,
+                        Key::Space => self.dispatch(game, InputEvent::StartGame),
+                        key => {
+                            self.held_keys.press(key);
+                            self.dispatch(game, InputEvent::Key { key, pressed: true });
                         }
                     }
                 }
-            } else {
-                game.handle_key(Key::Up, false);
-                game.handle_key(Key::Down, false);
-                game.handle_key(Key::Left, false);
-                game.handle_key(Key::Right, false);
             }
-            thread::sleep(time::Duration::from_millis(REFRESH_RATE));
+        }
+        true
+    }
 
-            if input.is_none() {
-                self.player_control_key = None;
+    /// Update the game by the time since the last frame and draw it.
+    fn frame(&mut self, game: &mut dyn GameHandler, now: Instant) {
+        let dt = now - self.last_frame_time;
+        self.last_frame_time = now;
+        self.profiler.record_frame(dt);
+        if let Ok(size) = termion::terminal_size() {
+            if self.terminal_size != Some(size) {
+                self.terminal_size = Some(size);
+                game.on_resize(size.0 as u32, size.1 as u32);
+            }
+        }
+        if let Some(game_dt) = self.runtime_state.next_dt(game, dt) {
+            let gamepad_events = self
+                .gamepad
+                .as_mut()
+                .map(GamepadInput::poll)
+                .unwrap_or_default();
+            for event in gamepad_events.into_iter().filter_map(start_on_space) {
+                self.dispatch(game, event);
             }
+            let update_start = Instant::now();
+            game.next_profiled(game_dt, &mut self.profiler);
+            self.profiler
+                .record("update", update_start, update_start.elapsed());
+            if let Some(recorder) = &mut self.recorder {
+                recorder.end_frame(game_dt);
+            }
+        }
+        let scene = game.scene();
+        self.display.debug_boxes = if self.debug_draw {
+            debug_boxes(scene, self.runtime_state.game_dt())
+        } else {
+            vec![]
+        };
+        self.display.panel = if self.inspector.is_open() {
+            self.inspector.lines(scene)
+        } else {
+            vec![]
+        };
+        let follow = match game.camera().target {
+            Some(CameraStrategy::CameraFollow(id)) => Some(id),
+            _ => None,
+        };
+        let debug = match (game.debug_str(), self.runtime_state.status()) {
+            (Some(debug), Some(status)) => Some(format!("{} {}", status, debug)),
+            (debug, status) => debug.or(status),
+        };
+        self.profiler.time("render", || {
+            self.display.next_scenes(&game.scenes(), debug, follow)
+        });
+        if self.capture.wants_frame() {
+            let frame = capture::rasterize_text(&self.display.last_frame);
+            if let Err(e) = self.capture.add_frame(frame, dt) {
+                log::error!("Unable to capture frame: {:#}", e);
+            }
+        }
+        game.on_profile(&self.profiler);
+    }
+
+    /// Start the game loop listening for player input and rendering the game.
+    pub fn start(&mut self, game: &mut dyn GameHandler) {
+        'running: loop {
+            let frame_start = Instant::now();
+
+            // Handle every key pressed since the last frame, so quick presses within one
+            // frame are all delivered.
+            while let Some(input) = self.stdin.next() {
+                let Ok(key) = input else {
+                    continue;
+                };
+                self.held_keys.input(frame_start);
+                if !self.handle_key(game, key) {
+                    break 'running;
+                }
+            }
+            for key in self.held_keys.release(frame_start) {
+                self.dispatch(
+                    game,
+                    InputEvent::Key {
+                        key,
+                        pressed: false,
+                    },
+                );
+            }
+
+            self.frame(game, frame_start);
+            if game.should_exit() {
+                break;
+            }
+            thread::sleep(self.frame_time.saturating_sub(frame_start.elapsed()));
+        }
 
-            let now = time::Instant::now();
-            if now - self.last_frame_time > Duration::from_millis(REFRESH_RATE) {
-                game.next(now - self.last_frame_time);
-                let scene = game.scene();
-                let entities = scene.get_entities_with_component(ComponentType::Render);
-                self.display.next(entities, game.debug_str());
-                self.last_frame_time = now;
+        game.on_exit();
+        if let Some(recorder) = &self.recorder {
+            if let Err(e) = recorder.save() {
+                log::error!("Unable to save input recording: {:#}", e);
             }
         }
+        if let Err(e) = self.capture.finish() {
+            log::error!("Unable to save frame recording: {:#}", e);
+        }
+        if let Some(path) = &self.trace_path {
+            if let Err(e) = self.profiler.save_chrome_trace(path) {
+                log::error!("Unable to save trace: {:#}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::gamepad::{GamepadButton, GamepadId, VirtualGamepad};
+
+    #[test]
+    fn test_gamepad_start_button_starts_the_game() {
+        let mut input = GamepadInput::new(Box::new(
+            VirtualGamepad::new(GamepadId(0))
+                .press(GamepadButton::Start)
+                .release(GamepadButton::Start),
+        ));
+        let events: Vec<InputEvent> = [input.poll(), input.poll()]
+            .concat()
+            .into_iter()
+            .filter_map(start_on_space)
+            .collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1], InputEvent::StartGame);
+        assert!(matches!(events[0], InputEvent::Gamepad(_)));
+        assert!(matches!(events[2], InputEvent::Gamepad(_)));
+    }
+
+    #[test]
+    fn test_held_keys_are_released_once() {
+        let start = Instant::now();
+        let mut held = HeldKeys::new(start);
+        assert!(held.release(start + KEY_RELEASE_DELAY * 2).is_empty());
+
+        held.input(start);
+        held.press(Key::Left);
+        held.press(Key::Left);
+        held.press(Key::Up);
+        assert!(held.release(start + KEY_RELEASE_DELAY / 2).is_empty());
+        assert_eq!(
+            held.release(start + KEY_RELEASE_DELAY * 2),
+            vec![Key::Left, Key::Up]
+        );
+        assert!(held.release(start + KEY_RELEASE_DELAY * 3).is_empty());
     }
 }
@@ -1,21 +1,36 @@
+use crate::font;
+use crate::runtime::camera::{CameraConfig, CameraController, CameraTargeting};
+use crate::runtime::debug_draw::DebugBox;
+use crate::runtime::hud::{Hud, HudCommand};
+use crate::scene::AtlasId;
 use crate::scene::Entity;
-use crate::scene::EntityId;
+use crate::scene::Scene;
+use crate::scene::SizeComponent;
 use crate::wgpu::texture;
 use cgmath::prelude::*;
 use cgmath::SquareMatrix;
-use std::f32::consts::PI;
+use std::iter;
+use std::marker::PhantomData;
 use std::mem;
-use std::{iter, sync::Arc};
+use std::ops::Range;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 use wgpu::util::DeviceExt;
-use winit::{event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
+use winit::window::Window;
 
-#[derive(Default, Copy, Clone)]
-pub enum CameraStrategy {
+pub use crate::runtime::camera::CameraStrategy;
+
+/// How entities without a sprite are drawn in the window.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// A square of the entity's colour.
     #[default]
-    AllEntities,
-    CameraFollow(EntityId),
+    Blocks,
+    /// The entity's `ascii_character` in the built-in bitmap font, tinted by its colour, for a
+    /// terminal look. Like the terminal, the character is repeated over the entity's size.
+    Glyphs,
 }
 
 #[repr(C)]
@@ -24,48 +39,27 @@ pub struct Vertex {
     position: [f32; 3],
 }
 
-fn gen_shape_buffer(points: u16, rotation_deg: f32, size: f32) -> (Vec<Vertex>, Vec<u16>) {
-    if points < 3 {
-        return (
-            vec![
-                Vertex {
-                    position: [-0.0868241, 0.49240386, 0.0],
-                },
-                Vertex {
-                    position: [-0.49513406, 0.06958647, 0.0],
-                },
-                Vertex {
-                    position: [-0.21918549, -0.44939706, 0.0],
-                },
-                Vertex {
-                    position: [0.35966998, -0.3473291, 0.0],
-                },
-                Vertex {
-                    position: [0.44147372, 0.2347359, 0.0],
-                },
-            ],
-            vec![0, 1, 4, 1, 2, 4, 2, 3, 4],
-        );
-    }
-
-    let mut vertices = Vec::with_capacity(points as usize);
-    let rotation_rad = rotation_deg.to_radians();
-    for i in 0..points {
-        let theta = 2.0 * PI * (i as f32) / (points as f32) + rotation_rad;
-        let x = size * theta.cos();
-        let y = size * theta.sin();
-        vertices.push(Vertex {
-            position: [x, y, 0.0],
-        });
-    }
-
-    let mut indices = Vec::with_capacity((points as usize - 2) * 3);
-    for i in 1..(points - 1) {
-        indices.push(0u16);
-        indices.push(i);
-        indices.push(i + 1);
-    }
-
+/// Size of one world unit in render space.
+const WORLD_SCALE: f32 = 0.1;
+
+/// A unit square anchored at its bottom-left corner, matching the origin of an entity's
+/// collision box. Instances scale it by the entity's `SizeComponent`.
+fn unit_quad() -> (Vec<Vertex>, Vec<u16>) {
+    let vertices = vec![
+        Vertex {
+            position: [0.0, 0.0, 0.0],
+        },
+        Vertex {
+            position: [1.0, 0.0, 0.0],
+        },
+        Vertex {
+            position: [1.0, 1.0, 0.0],
+        },
+        Vertex {
+            position: [0.0, 1.0, 0.0],
+        },
+    ];
+    let indices = vec![0, 1, 2, 0, 2, 3];
     (vertices, indices)
 }
 
@@ -91,11 +85,38 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::from_co
     cgmath::Vector4::new(0.0, 0.0, 0.5, 1.0),
 );
 
+/// Everything [`State`] needs to know about how to draw, set up by the window runtime.
+#[derive(Clone, Default)]
+pub(crate) struct RenderSettings {
+    pub(crate) camera_strategy: CameraStrategy,
+    pub(crate) render_mode: RenderMode,
+    pub(crate) projection: Projection,
+    pub(crate) atlases: Vec<image::DynamicImage>,
+    pub(crate) clear_color: wgpu::Color,
+    pub(crate) vsync: bool,
+}
+
+/// How the camera projects the world onto the window.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    /// A 3D perspective camera, moved back far enough to fit the view.
+    #[default]
+    Perspective,
+    /// A flat 2D camera where one world unit is `pixels_per_unit * zoom` pixels, whatever
+    /// the window size.
+    Orthographic { pixels_per_unit: f32, zoom: f32 },
+}
+
+/// Distance from the camera to the scene when following an entity.
+const FOLLOW_DISTANCE: f32 = 4.0;
+
 pub(crate) struct Camera {
     pub(crate) eye: cgmath::Point3<f32>,
     pub(crate) target: cgmath::Point3<f32>,
     pub(crate) up: cgmath::Vector3<f32>,
-    pub(crate) aspect: f32,
+    pub(crate) projection: Projection,
+    /// Size of the surface in pixels.
+    pub(crate) viewport: (f32, f32),
     pub(crate) fovy: f32,
     pub(crate) znear: f32,
     pub(crate) zfar: f32,
@@ -104,9 +125,177 @@ pub(crate) struct Camera {
 impl Camera {
     pub(crate) fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
         let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+        let proj = match self.projection {
+            Projection::Perspective => cgmath::perspective(
+                cgmath::Deg(self.fovy),
+                self.viewport.0 / self.viewport.1,
+                self.znear,
+                self.zfar,
+            ),
+            Projection::Orthographic {
+                pixels_per_unit,
+                zoom,
+            } => {
+                let render_units_per_pixel = WORLD_SCALE / (pixels_per_unit * zoom);
+                let half_width = self.viewport.0 / 2.0 * render_units_per_pixel;
+                let half_height = self.viewport.1 / 2.0 * render_units_per_pixel;
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.znear,
+                    self.zfar,
+                )
+            }
+        };
         proj * view
     }
+
+    /// Where a world position appears on screen, in pixels from the top-left, or `None` if
+    /// it is behind the camera.
+    pub(crate) fn world_to_screen(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let clip = self.build_view_projection_matrix()
+            * cgmath::Vector4::new(x * WORLD_SCALE, y * WORLD_SCALE, 0.0, 1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        Some((
+            (clip.x / clip.w + 1.0) / 2.0 * self.viewport.0,
+            (1.0 - clip.y / clip.w) / 2.0 * self.viewport.1,
+        ))
+    }
+
+    /// The world position seen at a point on screen, in pixels from the top-left, found by
+    /// casting a ray from the camera onto the plane entities are drawn on.
+    pub(crate) fn screen_to_world(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let inverse = self.build_view_projection_matrix().invert()?;
+        let ndc_x = x / self.viewport.0 * 2.0 - 1.0;
+        let ndc_y = 1.0 - y / self.viewport.1 * 2.0;
+        let unproject = |z: f32| {
+            let point = inverse * cgmath::Vector4::new(ndc_x, ndc_y, z, 1.0);
+            point.truncate() / point.w
+        };
+        let (near, far) = (unproject(0.0), unproject(1.0));
+        if near.z == far.z {
+            return None;
+        }
+        let point = near + (far - near) * (near.z / (near.z - far.z));
+        Some((point.x / WORLD_SCALE, point.y / WORLD_SCALE))
+    }
+
+    /// Half the visible width and height in world units, seen from `distance`.
+    pub(crate) fn half_view(&self, distance: f32) -> (f32, f32) {
+        match self.projection {
+            Projection::Perspective => {
+                let half_height = distance * (cgmath::Deg(self.fovy / 2.0)).tan() / WORLD_SCALE;
+                (half_height * self.viewport.0 / self.viewport.1, half_height)
+            }
+            Projection::Orthographic {
+                pixels_per_unit,
+                zoom,
+            } => (
+                self.viewport.0 / 2.0 / (pixels_per_unit * zoom),
+                self.viewport.1 / 2.0 / (pixels_per_unit * zoom),
+            ),
+        }
+    }
+
+    /// Point the camera at `(x, y)` in world units. `distance` is only used by the
+    /// perspective projection, as an orthographic view is the same from any distance.
+    pub(crate) fn look_at(&mut self, x: f32, y: f32, distance: f32) {
+        let distance = match self.projection {
+            Projection::Perspective => distance,
+            Projection::Orthographic { .. } => FOLLOW_DISTANCE,
+        };
+        self.eye = cgmath::Point3::new(x * WORLD_SCALE, y * WORLD_SCALE, distance);
+        self.target = cgmath::Point3::new(x * WORLD_SCALE, y * WORLD_SCALE, 0.0);
+    }
+}
+
+/// Moves the camera each frame with the same rules in every renderer: fit every entity in
+/// view, or follow an entity through a [`CameraController`].
+pub(crate) struct CameraRig {
+    pub(crate) camera: Camera,
+    pub(crate) strategy: CameraStrategy,
+    controller: CameraController,
+    targeting: CameraTargeting,
+}
+
+impl CameraRig {
+    pub(crate) fn new(
+        strategy: CameraStrategy,
+        projection: Projection,
+        viewport: (f32, f32),
+    ) -> CameraRig {
+        CameraRig {
+            camera: Camera {
+                eye: (0.0, 1.0, 10.0).into(),
+                target: (0.0, 0.0, 0.0).into(),
+                up: cgmath::Vector3::unit_y(),
+                projection,
+                viewport,
+                fovy: 45.0,
+                znear: 0.1,
+                zfar: 100.0,
+            },
+            strategy,
+            controller: CameraController::new(),
+            targeting: CameraTargeting::new(),
+        }
+    }
+
+    /// Move the camera on by `dt`. It frames every drawn entity in `entities`, or follows
+    /// one of `followable`, the entities of the scene whose ids the game knows.
+    pub(crate) fn update(
+        &mut self,
+        entities: &[&Entity],
+        followable: &[Entity],
+        camera_config: CameraConfig,
+        dt: Duration,
+    ) {
+        let camera_points = entities
+            .iter()
+            .filter(|e| e.components.render.is_some() || e.components.sprite.is_some())
+            .fold((0.0, 0.0, 0.0, 0.0), |mut acc, e| {
+                if let Some(position) = e.components.position {
+                    let size = e
+                        .components
+                        .size
+                        .unwrap_or(SizeComponent { x: 1.0, y: 1.0 });
+                    acc.0 = position.x.min(acc.0);
+                    acc.1 = (position.x + size.x).max(acc.1);
+                    acc.2 = position.y.min(acc.2);
+                    acc.3 = (position.y + size.y).max(acc.3);
+                }
+                acc
+            });
+        let camera_x_position = (camera_points.0 + camera_points.1) / 2.0;
+        let camera_y_position = (camera_points.2 + camera_points.3) / 2.0;
+
+        let followed = match camera_config.target.unwrap_or(self.strategy) {
+            CameraStrategy::CameraFollow(entity_id) => {
+                self.targeting.target(Some(entity_id), followable)
+            }
+            CameraStrategy::AllEntities => None,
+        };
+        let (target, velocity, distance) = match followed {
+            Some(followed) => (followed.position, followed.velocity, FOLLOW_DISTANCE),
+            None => {
+                let game_width = camera_points.1 - camera_points.0;
+                let z_depth = game_width / 8.1;
+                ((camera_x_position, camera_y_position), (0.0, 0.0), z_depth)
+            }
+        };
+        let (x, y) = self.controller.update(
+            &camera_config,
+            target,
+            velocity,
+            self.camera.half_view(distance),
+            dt.as_secs_f32(),
+        );
+        self.camera.look_at(x, y, distance);
+    }
 }
 
 #[repr(C)]
@@ -125,23 +314,30 @@ impl CameraUniform {
     pub(crate) fn update_view_proj(&mut self, camera: &Camera) {
         self.view_proj = (OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix()).into();
     }
-}
 
-pub(crate) struct InstancePosition {
-    pub(crate) position: cgmath::Vector3<f32>,
-    pub(crate) rotation: cgmath::Quaternion<f32>,
+    /// Map pixels on a `width` by `height` screen, with the origin at the bottom left.
+    pub(crate) fn update_screen(&mut self, width: f32, height: f32) {
+        self.view_proj =
+            (OPENGL_TO_WGPU_MATRIX * cgmath::ortho(0.0, width, 0.0, height, -1.0, 1.0)).into();
+    }
 }
 
+/// Everything the shader needs to draw one instance of the quad, packed into a single
+/// vertex buffer.
 #[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub(crate) struct InstancePositionRaw {
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct InstanceRaw {
+    /// Moves and scales the unit quad into render space.
     pub(crate) model: [[f32; 4]; 4],
+    pub(crate) color: [f32; 3],
+    /// Top-left and bottom-right corners of the instance's region of the bound texture.
+    pub(crate) uv: [f32; 4],
 }
 
-impl InstancePositionRaw {
+impl InstanceRaw {
     pub(crate) fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<InstancePositionRaw>() as wgpu::BufferAddress,
+            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
             // We need to switch from using a step mode of Vertex to Instance
             // This means that our shaders will only change to use the next
             // instance when the shader starts processing a new instance
@@ -171,50 +367,490 @@ impl InstancePositionRaw {
                     shader_location: 8,
                     format: wgpu::VertexFormat::Float32x4,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 19]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
 }
 
-impl InstancePosition {
-    pub(crate) fn to_raw(&self) -> InstancePositionRaw {
-        InstancePositionRaw {
-            model: (cgmath::Matrix4::from_translation(self.position)
-                * cgmath::Matrix4::from(self.rotation))
-            .into(),
+/// The whole texture, used for flat colours with the white texture.
+const FULL_UV: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// The region of the font atlas holding the glyph for `c`.
+fn glyph_uv(c: char) -> [f32; 4] {
+    let (glyph_x, glyph_y) = font::glyph_origin(c);
+    [
+        glyph_x as f32 / font::ATLAS_WIDTH as f32,
+        glyph_y as f32 / font::ATLAS_HEIGHT as f32,
+        (glyph_x + font::GLYPH_WIDTH) as f32 / font::ATLAS_WIDTH as f32,
+        (glyph_y + font::GLYPH_HEIGHT) as f32 / font::ATLAS_HEIGHT as f32,
+    ]
+}
+
+/// The texture an instance samples from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TextureSlot {
+    /// A single white texel, so the instance is drawn in its flat colour.
+    White,
+    /// The built-in font atlas.
+    Glyphs,
+    Atlas(AtlasId),
+}
+
+/// Instance data for every entity, rebuilt each frame into the same allocation.
+#[derive(Default)]
+pub(crate) struct Instances {
+    pub(crate) raw: Vec<InstanceRaw>,
+    pub(crate) textures: Vec<TextureSlot>,
+    /// Indices of the entities in draw order.
+    order: Vec<usize>,
+}
+
+impl Instances {
+    /// Build the instance data for each entity in layer order, sized and positioned like its
+    /// collision box.
+    /// `atlas_sizes` holds the pixel size of each loaded atlas; sprites referencing an atlas
+    /// that was never loaded fall back to `mode`.
+    pub fn rebuild(&mut self, entities: &[&Entity], atlas_sizes: &[(u32, u32)], mode: RenderMode) {
+        self.raw.clear();
+        self.textures.clear();
+        // Without a depth buffer later instances are drawn over earlier ones, so draw the
+        // highest layers last. Like `scene::sort_by_layer`, this only sorts when needed.
+        let mut order = mem::take(&mut self.order);
+        order.clear();
+        order.extend(0..entities.len());
+        if !order.is_sorted_by_key(|&i| entities[i].layer()) {
+            order.sort_by_key(|&i| entities[i].layer());
+        }
+        for &i in &order {
+            let e = entities[i];
+            let Some(position) = e.components.position else {
+                continue;
+            };
+            let size = e
+                .components
+                .size
+                .unwrap_or(SizeComponent { x: 1.0, y: 1.0 });
+
+            let sprite = e.components.sprite.and_then(|sprite| {
+                let (width, height) = *atlas_sizes.get(sprite.atlas.0 as usize)?;
+                let uv = [
+                    sprite.x as f32 / width as f32,
+                    sprite.y as f32 / height as f32,
+                    (sprite.x + sprite.width) as f32 / width as f32,
+                    (sprite.y + sprite.height) as f32 / height as f32,
+                ];
+                Some((TextureSlot::Atlas(sprite.atlas), uv))
+            });
+            if let Some((texture, uv)) = sprite {
+                // Sprites are drawn untinted.
+                let white = cgmath::Vector3::new(1.0, 1.0, 1.0);
+                self.push(
+                    (position.x, position.y),
+                    (size.x, size.y),
+                    white,
+                    uv,
+                    texture,
+                );
+                continue;
+            }
+
+            let Some(render) = e.components.render else {
+                continue;
+            };
+            match mode {
+                RenderMode::Blocks => self.push(
+                    (position.x, position.y),
+                    (size.x, size.y),
+                    render.rgb,
+                    FULL_UV,
+                    TextureSlot::White,
+                ),
+                RenderMode::Glyphs => {
+                    let uv = glyph_uv(render.ascii_character);
+                    // One glyph per whole cell of the entity.
+                    let columns = size.x.round().max(1.0);
+                    let rows = size.y.round().max(1.0);
+                    let cell = (size.x / columns, size.y / rows);
+                    for row in 0..rows as u32 {
+                        for column in 0..columns as u32 {
+                            self.push(
+                                (
+                                    position.x + column as f32 * cell.0,
+                                    position.y + row as f32 * cell.1,
+                                ),
+                                cell,
+                                render.rgb,
+                                uv,
+                                TextureSlot::Glyphs,
+                            );
+                        }
+                    }
+                }
+            }
         }
+        self.order = order;
+    }
+
+    /// Build the instance data for the overlay, in pixels on a screen `screen_height` high.
+    pub(crate) fn rebuild_hud(&mut self, hud: &Hud, screen_height: f32) {
+        self.raw.clear();
+        self.textures.clear();
+        // The overlay is drawn with y up like the world, so flip each top-left position to
+        // a bottom-left corner.
+        for command in hud.commands() {
+            match command {
+                HudCommand::Text {
+                    position,
+                    text,
+                    rgb,
+                    scale,
+                } => {
+                    let glyph = (
+                        font::GLYPH_WIDTH as f32 * scale,
+                        font::GLYPH_HEIGHT as f32 * scale,
+                    );
+                    let y = screen_height - position.1 - glyph.1;
+                    for (i, c) in text.chars().enumerate() {
+                        if c == ' ' {
+                            continue;
+                        }
+                        let x = position.0 + i as f32 * glyph.0;
+                        self.push_scaled(
+                            (x, y),
+                            glyph,
+                            1.0,
+                            *rgb,
+                            glyph_uv(c),
+                            TextureSlot::Glyphs,
+                        );
+                    }
+                }
+                HudCommand::Rect {
+                    position,
+                    size,
+                    rgb,
+                } => {
+                    let y = screen_height - position.1 - size.1;
+                    self.push_scaled(
+                        (position.0, y),
+                        *size,
+                        1.0,
+                        *rgb,
+                        FULL_UV,
+                        TextureSlot::White,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Add a quad with its bottom-left corner at `position`, in world units.
+    fn push(
+        &mut self,
+        position: (f32, f32),
+        size: (f32, f32),
+        color: cgmath::Vector3<f32>,
+        uv: [f32; 4],
+        texture: TextureSlot,
+    ) {
+        self.push_scaled(position, size, WORLD_SCALE, color, uv, texture);
+    }
+
+    /// Add a quad with its bottom-left corner at `position`, multiplied by `scale` to get
+    /// render space.
+    fn push_scaled(
+        &mut self,
+        position: (f32, f32),
+        size: (f32, f32),
+        scale: f32,
+        color: cgmath::Vector3<f32>,
+        uv: [f32; 4],
+        texture: TextureSlot,
+    ) {
+        self.raw.push(InstanceRaw {
+            model: [
+                [size.0 * scale, 0.0, 0.0, 0.0],
+                [0.0, size.1 * scale, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [position.0 * scale, position.1 * scale, 0.0, 1.0],
+            ],
+            color: color.into(),
+            uv,
+        });
+        self.textures.push(texture);
+    }
+
+    /// Runs of consecutive instances sharing a texture, each drawn with one call so entity
+    /// order is kept.
+    pub(crate) fn batches(&self) -> Vec<(TextureSlot, Range<u32>)> {
+        let mut batches: Vec<(TextureSlot, Range<u32>)> = vec![];
+        for (i, texture) in self.textures.iter().enumerate() {
+            let i = i as u32;
+            match batches.last_mut() {
+                Some((last, range)) if last == texture => range.end = i + 1,
+                _ => batches.push((*texture, i..i + 1)),
+            }
+        }
+        batches
     }
 }
 
-pub(crate) struct InstanceColor {
-    pub(crate) color: cgmath::Vector3<f32>,
+/// A vertex buffer kept between frames and only reallocated when it needs to grow.
+struct InstanceBuffer<T = InstanceRaw> {
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    _vertex: PhantomData<T>,
 }
 
+impl<T: bytemuck::Pod> InstanceBuffer<T> {
+    fn new(device: &wgpu::Device, capacity: usize) -> InstanceBuffer<T> {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: (capacity * mem::size_of::<T>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        InstanceBuffer {
+            buffer,
+            capacity,
+            _vertex: PhantomData,
+        }
+    }
+
+    fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[T]) {
+        if instances.len() > self.capacity {
+            *self = InstanceBuffer::new(device, instances.len().next_power_of_two());
+        }
+        if !instances.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+        }
+    }
+}
+
+/// A vertex of the collision debug overlay's lines, in render space.
 #[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub(crate) struct InstanceColorRaw {
-    pub(crate) model: [f32; 3],
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 3],
 }
 
-impl InstanceColorRaw {
-    pub(crate) fn desc() -> wgpu::VertexBufferLayout<'static> {
+impl LineVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+            wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
         wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<InstanceColorRaw>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[wgpu::VertexAttribute {
-                offset: 0,
-                shader_location: 9,
-                format: wgpu::VertexFormat::Float32x3,
-            }],
+            array_stride: mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+/// Colour of a debug box outline.
+const DEBUG_BOX_COLOR: [f32; 3] = [0.0, 0.8, 0.0];
+/// Colour of the outline of an entity that collided in the latest collision pass.
+const DEBUG_COLLIDING_COLOR: [f32; 3] = [1.0, 0.0, 0.0];
+const DEBUG_VELOCITY_COLOR: [f32; 3] = [0.0, 0.0, 1.0];
+/// Velocity lines show how far the entity moves in this long.
+const DEBUG_VELOCITY_SECONDS: f32 = 0.5;
+
+/// Line list vertices outlining each box and showing its velocity from the entity's centre.
+fn debug_lines(boxes: &[DebugBox]) -> Vec<LineVertex> {
+    let vertex = |x: f32, y: f32, color| LineVertex {
+        position: [x * WORLD_SCALE, y * WORLD_SCALE, 0.0],
+        color,
+    };
+    let mut lines = vec![];
+    for debug_box in boxes {
+        let color = if debug_box.colliding {
+            DEBUG_COLLIDING_COLOR
+        } else {
+            DEBUG_BOX_COLOR
+        };
+        let (x, y) = (&debug_box.x, &debug_box.y);
+        let corners = [
+            (x.start, y.start),
+            (x.end, y.start),
+            (x.end, y.end),
+            (x.start, y.end),
+        ];
+        for i in 0..corners.len() {
+            let (from, to) = (corners[i], corners[(i + 1) % corners.len()]);
+            lines.push(vertex(from.0, from.1, color));
+            lines.push(vertex(to.0, to.1, color));
+        }
+
+        let (centre_x, centre_y) = debug_box.centre;
+        let (velocity_x, velocity_y) = debug_box.velocity;
+        if velocity_x != 0.0 || velocity_y != 0.0 {
+            lines.push(vertex(centre_x, centre_y, DEBUG_VELOCITY_COLOR));
+            lines.push(vertex(
+                centre_x + velocity_x * DEBUG_VELOCITY_SECONDS,
+                centre_y + velocity_y * DEBUG_VELOCITY_SECONDS,
+                DEBUG_VELOCITY_COLOR,
+            ));
+        }
+    }
+    lines
+}
+
+/// A texture and the bind group used to sample it.
+struct BoundTexture {
+    texture: texture::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+impl BoundTexture {
+    fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture: texture::Texture,
+    ) -> BoundTexture {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("texture_bind_group"),
+        });
+        BoundTexture {
+            texture,
+            bind_group,
         }
     }
+
+    fn size(&self) -> (u32, u32) {
+        let size = self.texture.texture.size();
+        (size.width, size.height)
+    }
 }
 
-impl InstanceColor {
-    pub(crate) fn to_raw(&self) -> InstanceColorRaw {
-        InstanceColorRaw {
-            model: self.color.into(),
+/// A buffer the rendered frame is copied into so it can be read on the CPU, reused until the
+/// window is resized.
+struct Readback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    /// Rows are padded to the alignment wgpu requires for copies.
+    padded_bytes_per_row: u32,
+}
+
+impl Readback {
+    /// Whether frames in `format` can be read back, which needs 8-bit RGBA or BGRA texels.
+    fn supports(format: wgpu::TextureFormat) -> bool {
+        matches!(
+            format,
+            wgpu::TextureFormat::Rgba8Unorm
+                | wgpu::TextureFormat::Rgba8UnormSrgb
+                | wgpu::TextureFormat::Bgra8Unorm
+                | wgpu::TextureFormat::Bgra8UnormSrgb
+        )
+    }
+
+    fn new(device: &wgpu::Device, width: u32, height: u32) -> Readback {
+        let padded_bytes_per_row = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Readback {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+        }
+    }
+
+    fn copy(&self, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Wait for the copy and convert it from the surface `format` to RGBA.
+    fn read(&self, device: &wgpu::Device, format: wgpu::TextureFormat) -> Option<image::RgbaImage> {
+        let slice = self.buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        if let Err(e) = device.poll(wgpu::PollType::Wait) {
+            log::error!("Unable to capture frame {}", e);
+            return None;
+        }
+        match receiver.try_recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                log::error!("Unable to capture frame {}", e);
+                return None;
+            }
+            Err(_) => {
+                log::error!("Unable to capture frame, the readback buffer wasn't mapped");
+                return None;
+            }
+        }
+        let bgra = matches!(
+            format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+        let data = slice.get_mapped_range();
+        let mut frame = image::RgbaImage::new(self.width, self.height);
+        for (y, row) in data
+            .chunks(self.padded_bytes_per_row as usize)
+            .take(self.height as usize)
+            .enumerate()
+        {
+            for (x, texel) in row.chunks(4).take(self.width as usize).enumerate() {
+                let rgba = if bgra {
+                    [texel[2], texel[1], texel[0], 255]
+                } else {
+                    [texel[0], texel[1], texel[2], 255]
+                };
+                frame.put_pixel(x as u32, y as u32, image::Rgba(rgba));
+            }
         }
+        drop(data);
+        self.buffer.unmap();
+        Some(frame)
     }
 }
 
@@ -224,36 +860,52 @@ pub struct State {
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     is_surface_configured: bool,
+    can_capture: bool,
+    readback: Option<Readback>,
     render_pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     num_indices: u32,
-    #[allow(dead_code)]
-    diffuse_texture: texture::Texture,
-    diffuse_bind_group: wgpu::BindGroup,
-    instance_positions: Vec<InstancePosition>,
-    instance_colors: Vec<InstanceColor>,
-    instance_positions_buffer: wgpu::Buffer,
-    instance_colors_buffer: wgpu::Buffer,
-    camera_strategy: CameraStrategy,
-
-    vertices: Vec<Vertex>,
-    indices: Vec<u16>,
-
-    camera: Camera,
+    white_texture: BoundTexture,
+    glyph_texture: BoundTexture,
+    render_mode: RenderMode,
+    clear_color: wgpu::Color,
+    atlases: Vec<BoundTexture>,
+    batches: Vec<(TextureSlot, Range<u32>)>,
+    atlas_sizes: Vec<(u32, u32)>,
+    instances: Instances,
+    instance_buffer: InstanceBuffer,
+
+    camera_rig: CameraRig,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
+
+    debug_pipeline: wgpu::RenderPipeline,
+    debug_line_buffer: InstanceBuffer<LineVertex>,
+    debug_line_count: u32,
+
+    hud_instances: Instances,
+    hud_instance_buffer: InstanceBuffer,
+    hud_batches: Vec<(TextureSlot, Range<u32>)>,
+    hud_camera_buffer: wgpu::Buffer,
+    hud_camera_bind_group: wgpu::BindGroup,
     pub(crate) window: Arc<Window>,
-    renderable_entities: Vec<Entity>,
 }
 
 impl State {
     pub(crate) async fn new(
         window: Arc<Window>,
-        renderable_entities: Vec<Entity>,
-        camera_strategy: CameraStrategy,
+        settings: RenderSettings,
     ) -> anyhow::Result<State> {
+        let RenderSettings {
+            camera_strategy,
+            render_mode,
+            projection,
+            atlases,
+            clear_color,
+            vsync,
+        } = settings;
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
@@ -303,21 +955,31 @@ impl State {
             .copied()
             .find(|f| f.is_srgb())
             .unwrap_or(surface_caps.formats[0]);
+        // Frames are captured by copying the surface texture into a buffer and waiting for
+        // it, which the web can't do.
+        let can_capture = cfg!(not(target_arch = "wasm32"))
+            && surface_caps.usages.contains(wgpu::TextureUsages::COPY_SRC)
+            && Readback::supports(surface_format);
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage: if can_capture {
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+            },
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            // Both automatic modes are supported everywhere, falling back as needed.
+            present_mode: if vsync {
+                wgpu::PresentMode::AutoVsync
+            } else {
+                wgpu::PresentMode::AutoNoVsync
+            },
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
 
-        let diffuse_bytes = include_bytes!("happy-tree.png");
-        let diffuse_texture =
-            texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "happy-tree.png").unwrap();
-
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
@@ -341,33 +1003,46 @@ impl State {
                 label: Some("texture_bind_group_layout"),
             });
 
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
+        let white = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            1,
+            1,
+            image::Rgba([255, 255, 255, 255]),
+        ));
+        let white_texture = BoundTexture::new(
+            &device,
+            &texture_bind_group_layout,
+            texture::Texture::from_image(&device, &queue, &white, Some("white"))?,
+        );
+        let glyph_texture = BoundTexture::new(
+            &device,
+            &texture_bind_group_layout,
+            texture::Texture::from_image(
+                &device,
+                &queue,
+                &image::DynamicImage::ImageRgba8(font::atlas_image()),
+                Some("glyphs"),
+            )?,
+        );
+        let atlases = atlases
+            .iter()
+            .map(|atlas| {
+                let texture = texture::Texture::from_image(&device, &queue, atlas, Some("atlas"))?;
+                Ok(BoundTexture::new(
+                    &device,
+                    &texture_bind_group_layout,
+                    texture,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
 
-        let camera = Camera {
-            eye: (0.0, 1.0, 10.0).into(),
-            target: (0.0, 0.0, 0.0).into(),
-            up: cgmath::Vector3::unit_y(),
-            aspect: config.width as f32 / config.height as f32,
-            fovy: 45.0,
-            znear: 0.1,
-            zfar: 100.0,
-        };
+        let camera_rig = CameraRig::new(
+            camera_strategy,
+            projection,
+            (config.width as f32, config.height as f32),
+        );
 
         let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update_view_proj(&camera);
+        camera_uniform.update_view_proj(&camera_rig.camera);
 
         let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Camera Buffer"),
@@ -399,6 +1074,24 @@ impl State {
             label: Some("camera_bind_group"),
         });
 
+        // The overlay is drawn with the same pipeline, through a camera that maps pixels.
+        let mut hud_camera_uniform = CameraUniform::new();
+        hud_camera_uniform.update_screen(config.width as f32, config.height as f32);
+        let hud_camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("HUD Camera Buffer"),
+            contents: bytemuck::cast_slice(&[hud_camera_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let hud_camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: hud_camera_buffer.as_entire_binding(),
+            }],
+            label: Some("hud_camera_bind_group"),
+        });
+        let hud_instance_buffer = InstanceBuffer::new(&device, 256);
+
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
             source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
@@ -417,11 +1110,7 @@ impl State {
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: Some("vs_main"),
-                buffers: &[
-                    Vertex::desc(),
-                    InstancePositionRaw::desc(),
-                    InstanceColorRaw::desc(),
-                ],
+                buffers: &[Vertex::desc(), InstanceRaw::desc()],
                 compilation_options: Default::default(),
             },
             fragment: Some(wgpu::FragmentState {
@@ -429,10 +1118,7 @@ impl State {
                 entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState {
                     format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
                 compilation_options: Default::default(),
@@ -463,7 +1149,47 @@ impl State {
             cache: None,
         });
 
-        let (vertices, indices) = gen_shape_buffer(4, 45.0, 0.08);
+        let debug_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Debug Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("debug.wgsl").into()),
+        });
+        let debug_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Debug Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let debug_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Debug Pipeline"),
+            layout: Some(&debug_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &debug_shader,
+                entry_point: Some("vs_main"),
+                buffers: &[LineVertex::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &debug_shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let debug_line_buffer = InstanceBuffer::new(&device, 256);
+
+        let (vertices, indices) = unit_quad();
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
@@ -477,52 +1203,8 @@ impl State {
         });
         let num_indices = indices.len() as u32;
 
-        let (instance_positions, instance_colors): (Vec<InstancePosition>, Vec<InstanceColor>) =
-            renderable_entities
-                .iter()
-                .map(|e| {
-                    let position = e.components.position.unwrap();
-                    let rotation = cgmath::Quaternion::from_axis_angle(
-                        cgmath::Vector3::unit_z(),
-                        cgmath::Deg(0.0),
-                    );
-                    let color = e.components.render.unwrap().rgb;
-
-                    let position_3d = cgmath::Vector3 {
-                        x: position.x as f32 * 0.1,
-                        y: position.y as f32 * 0.1,
-                        z: 0.0,
-                    };
-                    (
-                        InstancePosition {
-                            position: position_3d,
-                            rotation,
-                        },
-                        InstanceColor { color },
-                    )
-                })
-                .unzip();
-        // .collect::<Vec<_>>();
-
-        let instance_positions_raw = instance_positions
-            .iter()
-            .map(InstancePosition::to_raw)
-            .collect::<Vec<_>>();
-        let instance_colors_raw = instance_colors
-            .iter()
-            .map(InstanceColor::to_raw)
-            .collect::<Vec<_>>();
-        let instance_positions_buffer =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Instance Position Buffer"),
-                contents: bytemuck::cast_slice(&instance_positions_raw),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-        let instance_colors_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Instance Color Buffer"),
-            contents: bytemuck::cast_slice(&instance_colors_raw),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+        let atlas_sizes = atlases.iter().map(BoundTexture::size).collect();
+        let instance_buffer = InstanceBuffer::new(&device, 1024);
 
         Ok(Self {
             surface,
@@ -530,25 +1212,34 @@ impl State {
             queue,
             config,
             is_surface_configured: false,
+            can_capture,
+            readback: None,
             render_pipeline,
             vertex_buffer,
             index_buffer,
             num_indices,
-            diffuse_texture,
-            diffuse_bind_group,
-            vertices,
-            indices,
-            camera,
+            white_texture,
+            glyph_texture,
+            render_mode,
+            clear_color,
+            atlases,
+            camera_rig,
             camera_buffer,
             camera_bind_group,
             camera_uniform,
-            instance_positions,
-            instance_colors,
-            instance_positions_buffer,
-            instance_colors_buffer,
+            batches: vec![],
+            atlas_sizes,
+            instances: Instances::default(),
+            instance_buffer,
+            debug_pipeline,
+            debug_line_buffer,
+            debug_line_count: 0,
+            hud_instances: Instances::default(),
+            hud_instance_buffer,
+            hud_batches: vec![],
+            hud_camera_buffer,
+            hud_camera_bind_group,
             window,
-            renderable_entities,
-            camera_strategy,
         })
     }
 
@@ -563,128 +1254,64 @@ impl State {
             self.config.height = height;
             self.surface.configure(&self.device, &self.config);
 
-            self.camera.aspect = self.config.width as f32 / self.config.height as f32;
+            self.camera_rig.camera.viewport = (self.config.width as f32, self.config.height as f32);
+
+            let mut hud_camera_uniform = CameraUniform::new();
+            hud_camera_uniform.update_screen(width as f32, height as f32);
+            self.queue.write_buffer(
+                &self.hud_camera_buffer,
+                0,
+                bytemuck::cast_slice(&[hud_camera_uniform]),
+            );
         }
     }
 
-    pub(crate) fn handle_key(&mut self, event_loop: &ActiveEventLoop, key: KeyCode, pressed: bool) {
-        if key == KeyCode::Escape && pressed {
-            event_loop.exit();
-        }
+    /// Size of the surface in pixels.
+    pub(crate) fn size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
     }
 
-    pub(crate) fn update(&mut self, renderable_entities: Vec<Entity>) {
-        self.renderable_entities = renderable_entities;
+    /// Replace the overlay drawn over the scene.
+    pub(crate) fn update_hud(&mut self, hud: &Hud) {
+        self.hud_instances
+            .rebuild_hud(hud, self.config.height as f32);
+        self.hud_instance_buffer
+            .write(&self.device, &self.queue, &self.hud_instances.raw);
+        self.hud_batches = self.hud_instances.batches();
+    }
 
-        let (instance_positions, instance_colors): (Vec<InstancePosition>, Vec<InstanceColor>) =
-            self.renderable_entities
-                .iter()
-                .map(|e| {
-                    let position = e.components.position.unwrap();
-                    let render = e.components.render.unwrap();
-                    let rotation = cgmath::Quaternion::from_axis_angle(
-                        cgmath::Vector3::unit_z(),
-                        cgmath::Deg(0.0),
-                    );
+    /// Replace the collision debug overlay's lines. An empty slice hides the overlay.
+    pub(crate) fn update_debug(&mut self, boxes: &[DebugBox]) {
+        let lines = debug_lines(boxes);
+        self.debug_line_buffer
+            .write(&self.device, &self.queue, &lines);
+        self.debug_line_count = lines.len() as u32;
+    }
 
-                    let position_3d = cgmath::Vector3 {
-                        x: position.x as f32 * 0.1,
-                        y: position.y as f32 * 0.1,
-                        z: 0.0,
-                    };
-                    (
-                        InstancePosition {
-                            position: position_3d,
-                            rotation,
-                        },
-                        InstanceColor { color: render.rgb },
-                    )
-                })
-                .unzip();
-        self.instance_positions = instance_positions;
-        self.instance_colors = instance_colors;
-        let instance_position_data = self
-            .instance_positions
-            .iter()
-            .map(InstancePosition::to_raw)
-            .collect::<Vec<_>>();
-        let instance_positions_buffer =
-            self.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Instance Position Buffer"),
-                    contents: bytemuck::cast_slice(&instance_position_data),
-                    usage: wgpu::BufferUsages::VERTEX,
-                });
-
-        let instance_colors_data = self
-            .instance_colors
-            .iter()
-            .map(InstanceColor::to_raw)
-            .collect::<Vec<_>>();
-        let instance_colors_buffer =
-            self.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Instance Position Buffer"),
-                    contents: bytemuck::cast_slice(&instance_colors_data),
-                    usage: wgpu::BufferUsages::VERTEX,
-                });
-        // Log the instance positions to stdout so we can see things are moving when keys are hit.
-        self.instance_positions_buffer = instance_positions_buffer;
-        self.instance_colors_buffer = instance_colors_buffer;
-
-        let camera_points =
-            self.renderable_entities
-                .iter()
-                .fold((0.0, 0.0, 0.0, 0.0), |mut acc, e| {
-                    if let Some(position) = e.components.position {
-                        if position.x < acc.0 {
-                            acc.0 = position.x;
-                        }
-                        if position.x > acc.1 {
-                            acc.1 = position.x;
-                        }
-                        if position.y < acc.2 {
-                            acc.2 = position.y;
-                        }
-                        if position.y > acc.3 {
-                            acc.3 = position.y;
-                        }
-                    }
-                    acc
-                });
-        let camera_x_position = (camera_points.0 + camera_points.1) / 2.0;
-        let camera_y_position = (1.0 - camera_points.2 + camera_points.3) / 2.0;
+    /// Where a world position appears on screen, in pixels from the top-left.
+    pub(crate) fn world_to_screen(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        self.camera_rig.camera.world_to_screen(x, y)
+    }
 
-        match self.camera_strategy {
-            CameraStrategy::CameraFollow(entity_id) => {
-                let entity = self
-                    .renderable_entities
-                    .iter()
-                    .find(|e| e.id == entity_id)
-                    .unwrap(); // what do we do in the case the entity doesn't exist?
-                let camera_follow_position = entity.components.position.unwrap();
-                self.camera.eye = cgmath::Point3::new(
-                    camera_follow_position.x as f32 * 0.1,
-                    camera_follow_position.y as f32 * 0.1,
-                    4.0,
-                );
-                self.camera.target = cgmath::Point3::new(
-                    camera_follow_position.x as f32 * 0.1,
-                    camera_follow_position.y as f32 * 0.1,
-                    0.0,
-                );
-                self.camera_uniform.update_view_proj(&self.camera);
-            }
-            CameraStrategy::AllEntities => {
-                let game_width = camera_points.1 - camera_points.0;
-                let z_depth = game_width as f32 / 8.1;
-                self.camera.eye =
-                    cgmath::Point3::new(camera_x_position * 0.1, camera_y_position * 0.1, z_depth);
-                self.camera.target =
-                    cgmath::Point3::new(camera_x_position * 0.1, camera_y_position * 0.1, 0.0);
-                self.camera_uniform.update_view_proj(&self.camera);
-            }
-        }
+    /// The world position under a point on screen, in pixels from the top-left.
+    pub(crate) fn screen_to_world(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        self.camera_rig.camera.screen_to_world(x, y)
+    }
+
+    /// Prepare the entities of `scenes`, bottom first, and move the camera on by `dt`.
+    pub(crate) fn update(&mut self, scenes: &[&Scene], camera_config: CameraConfig, dt: Duration) {
+        let entities: Vec<&Entity> = scenes.iter().flat_map(|scene| scene.entities()).collect();
+        self.instances
+            .rebuild(&entities, &self.atlas_sizes, self.render_mode);
+        self.instance_buffer
+            .write(&self.device, &self.queue, &self.instances.raw);
+        self.batches = self.instances.batches();
+
+        let followable = scenes.last().map_or(&[][..], |scene| scene.entities());
+        self.camera_rig
+            .update(&entities, followable, camera_config, dt);
+        self.camera_uniform
+            .update_view_proj(&self.camera_rig.camera);
 
         self.queue.write_buffer(
             &self.camera_buffer,
@@ -693,12 +1320,16 @@ impl State {
         );
     }
 
-    pub(crate) fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+    /// Draw the frame, returning a copy of it if `capture` is set and the surface supports it.
+    pub(crate) fn render(
+        &mut self,
+        capture: bool,
+    ) -> Result<Option<image::RgbaImage>, wgpu::SurfaceError> {
         self.window.request_redraw();
 
         // We can't render unless the surface is configured
         if !self.is_surface_configured {
-            return Ok(());
+            return Ok(None);
         }
 
         let output = self.surface.get_current_texture()?;
@@ -719,12 +1350,7 @@ impl State {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 1.0,
-                            g: 1.0,
-                            b: 1.0,
-                            a: 1.0,
-                        }),
+                        load: wgpu::LoadOp::Clear(self.clear_color),
                         store: wgpu::StoreOp::Store,
                     },
                     depth_slice: None,
@@ -735,22 +1361,348 @@ impl State {
             });
 
             render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
             render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.instance_positions_buffer.slice(..));
-            render_pass.set_vertex_buffer(2, self.instance_colors_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, self.instance_buffer.buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(
-                0..self.num_indices,
-                0,
-                0..self.instance_positions.len() as _,
-            );
+            self.draw_batches(&mut render_pass, &self.batches);
+
+            if self.debug_line_count > 0 {
+                render_pass.set_pipeline(&self.debug_pipeline);
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.debug_line_buffer.buffer.slice(..));
+                render_pass.draw(0..self.debug_line_count, 0..1);
+                render_pass.set_pipeline(&self.render_pipeline);
+                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            }
+
+            render_pass.set_bind_group(1, &self.hud_camera_bind_group, &[]);
+            render_pass.set_vertex_buffer(1, self.hud_instance_buffer.buffer.slice(..));
+            self.draw_batches(&mut render_pass, &self.hud_batches);
         }
 
+        let capture = capture && self.can_capture;
+        if capture {
+            let (width, height) = (self.config.width, self.config.height);
+            let readback = match self.readback.take() {
+                Some(readback) if (readback.width, readback.height) == (width, height) => readback,
+                _ => Readback::new(&self.device, width, height),
+            };
+            readback.copy(&mut encoder, &output.texture);
+            self.readback = Some(readback);
+        }
         self.queue.submit(iter::once(encoder.finish()));
+        let frame = match &self.readback {
+            Some(readback) if capture => readback.read(&self.device, self.config.format),
+            _ => None,
+        };
+        if capture && frame.is_none() {
+            // The buffer may still be waiting to map, so the next capture starts a new one.
+            self.readback = None;
+        }
         output.present();
 
-        Ok(())
+        Ok(frame)
+    }
+
+    fn draw_batches(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        batches: &[(TextureSlot, Range<u32>)],
+    ) {
+        for (texture, instances) in batches {
+            let bound = match texture {
+                TextureSlot::White => &self.white_texture,
+                TextureSlot::Glyphs => &self.glyph_texture,
+                TextureSlot::Atlas(id) => &self.atlases[id.0 as usize],
+            };
+            render_pass.set_bind_group(0, &bound.bind_group, &[]);
+            render_pass.draw_indexed(0..self.num_indices, 0, instances.clone());
+        }
+    }
+}
+
+/// Entry points for the benchmarks in `benches/`, behind the `bench` feature.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    use super::{Instances, RenderMode};
+    use crate::scene::Entity;
+
+    /// The window's per-frame instance data, reused between rebuilds.
+    #[derive(Default)]
+    pub struct InstanceBuilder(Instances);
+
+    impl InstanceBuilder {
+        /// Rebuild the instance data for `entities`, returning the number of instances.
+        pub fn rebuild(&mut self, entities: &[&Entity], mode: RenderMode) -> usize {
+            self.0.rebuild(entities, &[], mode);
+            self.0.raw.len()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scene::{EntityId, SpriteComponent};
+    use wgpu::naga;
+
+    fn build(entities: &[Entity], atlas_sizes: &[(u32, u32)], mode: RenderMode) -> Instances {
+        let mut instances = Instances::default();
+        let entities: Vec<&Entity> = entities.iter().collect();
+        instances.rebuild(&entities, atlas_sizes, mode);
+        instances
+    }
+
+    #[test]
+    fn test_instances_scale_with_size() {
+        let entity = Entity::from_tuples(
+            EntityId(0),
+            (2.0, 3.0),
+            (0.0, 0.0),
+            (3.0, 1.0),
+            Some('#'),
+            false,
+        );
+        let instances = build(&[entity], &[], RenderMode::Blocks);
+        assert_eq!(instances.raw.len(), 1);
+
+        let model = cgmath::Matrix4::from(instances.raw[0].model);
+        let bottom_left = model * cgmath::Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let top_right = model * cgmath::Vector4::new(1.0, 1.0, 0.0, 1.0);
+        assert!((bottom_left.x - 0.2).abs() < 1e-6 && (bottom_left.y - 0.3).abs() < 1e-6);
+        assert!((top_right.x - 0.5).abs() < 1e-6 && (top_right.y - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sprite_uvs_and_batches() {
+        let flat = Entity::from_tuples(
+            EntityId(0),
+            (0.0, 0.0),
+            (0.0, 0.0),
+            (1.0, 1.0),
+            Some('#'),
+            false,
+        );
+        let mut sprite =
+            Entity::from_tuples(EntityId(1), (1.0, 0.0), (0.0, 0.0), (1.0, 1.0), None, false);
+        sprite.components.sprite = Some(SpriteComponent {
+            atlas: AtlasId(0),
+            x: 16,
+            y: 0,
+            width: 16,
+            height: 32,
+        });
+        let mut missing_atlas = sprite;
+        missing_atlas.components.sprite.as_mut().unwrap().atlas = AtlasId(1);
+
+        let instances = build(
+            &[flat, sprite, sprite, missing_atlas, flat],
+            &[(64, 32)],
+            RenderMode::Blocks,
+        );
+        // The sprite without a loaded atlas or render component is skipped.
+        assert_eq!(instances.raw.len(), 4);
+        assert_eq!(instances.raw[1].uv, [0.25, 0.0, 0.5, 1.0]);
+        assert_eq!(
+            instances.batches(),
+            vec![
+                (TextureSlot::White, 0..1),
+                (TextureSlot::Atlas(AtlasId(0)), 1..3),
+                (TextureSlot::White, 3..4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shader_is_valid() {
+        for source in [include_str!("shader.wgsl"), include_str!("debug.wgsl")] {
+            let module = naga::front::wgsl::parse_str(source).unwrap();
+            naga::valid::Validator::new(
+                naga::valid::ValidationFlags::all(),
+                naga::valid::Capabilities::empty(),
+            )
+            .validate(&module)
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_debug_lines_outline_boxes_and_velocities() {
+        let still = DebugBox {
+            id: EntityId(0),
+            x: 0.0..1.0,
+            y: 0.0..2.0,
+            centre: (0.5, 1.0),
+            velocity: (0.0, 0.0),
+            colliding: false,
+        };
+        let moving = DebugBox {
+            id: EntityId(1),
+            velocity: (4.0, 0.0),
+            colliding: true,
+            ..still.clone()
+        };
+
+        let lines = debug_lines(&[still, moving]);
+        // Four edges each, plus a velocity line for the moving box.
+        assert_eq!(lines.len(), 8 + 8 + 2);
+        assert!(lines[..8].iter().all(|v| v.color == DEBUG_BOX_COLOR));
+        assert!(lines[8..16]
+            .iter()
+            .all(|v| v.color == DEBUG_COLLIDING_COLOR));
+        assert_eq!(lines[2].position, [1.0 * WORLD_SCALE, 0.0, 0.0]);
+        assert_eq!(
+            lines[4].position,
+            [1.0 * WORLD_SCALE, 2.0 * WORLD_SCALE, 0.0]
+        );
+        assert_eq!(
+            (lines[16].position, lines[17].position),
+            (
+                [0.5 * WORLD_SCALE, 1.0 * WORLD_SCALE, 0.0],
+                [2.5 * WORLD_SCALE, 1.0 * WORLD_SCALE, 0.0]
+            )
+        );
+    }
+
+    #[test]
+    fn test_glyph_mode_repeats_character_over_size() {
+        let platform = Entity::from_tuples(
+            EntityId(0),
+            (2.0, 3.0),
+            (0.0, 0.0),
+            (3.0, 1.0),
+            Some('A'),
+            false,
+        );
+        let instances = build(&[platform], &[], RenderMode::Glyphs);
+
+        assert_eq!(instances.batches(), vec![(TextureSlot::Glyphs, 0..3)]);
+        let xs: Vec<f32> = instances.raw.iter().map(|i| i.model[3][0]).collect();
+        assert_eq!(xs, vec![0.2, 0.3, 0.4]);
+        // 'A' is the second glyph of the third row.
+        assert_eq!(
+            instances.raw[0].uv,
+            [6.0 / 96.0, 20.0 / 60.0, 12.0 / 96.0, 30.0 / 60.0]
+        );
+    }
+
+    #[test]
+    fn test_orthographic_projection_maps_units_to_pixels() {
+        let mut camera = Camera {
+            eye: (0.0, 0.0, 0.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            projection: Projection::Orthographic {
+                pixels_per_unit: 16.0,
+                zoom: 2.0,
+            },
+            viewport: (800.0, 600.0),
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+        };
+        camera.look_at(10.0, 5.0, 0.0);
+
+        let to_pixels = |x: f32, y: f32| {
+            let clip = camera.build_view_projection_matrix()
+                * cgmath::Vector4::new(x * WORLD_SCALE, y * WORLD_SCALE, 0.0, 1.0);
+            (
+                (clip.x / clip.w + 1.0) * 400.0,
+                (clip.y / clip.w + 1.0) * 300.0,
+            )
+        };
+        let (centre_x, centre_y) = to_pixels(10.0, 5.0);
+        assert!((centre_x - 400.0).abs() < 1e-3 && (centre_y - 300.0).abs() < 1e-3);
+        let (right_x, up_y) = to_pixels(11.0, 6.0);
+        assert!((right_x - centre_x - 32.0).abs() < 1e-3);
+        assert!((up_y - centre_y - 32.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_screen_and_world_positions_round_trip() {
+        for projection in [
+            Projection::Perspective,
+            Projection::Orthographic {
+                pixels_per_unit: 16.0,
+                zoom: 1.0,
+            },
+        ] {
+            let mut camera = Camera {
+                eye: (0.0, 0.0, 0.0).into(),
+                target: (0.0, 0.0, 0.0).into(),
+                up: cgmath::Vector3::unit_y(),
+                projection,
+                viewport: (800.0, 600.0),
+                fovy: 45.0,
+                znear: 0.1,
+                zfar: 100.0,
+            };
+            camera.look_at(10.0, 5.0, 2.0);
+
+            let (x, y) = camera.world_to_screen(10.0, 5.0).unwrap();
+            assert!((x - 400.0).abs() < 1e-3 && (y - 300.0).abs() < 1e-3);
+            let (above_x, above_y) = camera.world_to_screen(12.0, 6.0).unwrap();
+            assert!(above_x > x && above_y < y);
+            let (world_x, world_y) = camera.screen_to_world(above_x, above_y).unwrap();
+            assert!((world_x - 12.0).abs() < 1e-3 && (world_y - 6.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_instances_are_drawn_in_layer_order() {
+        let mut player = Entity::from_tuples(
+            EntityId(0),
+            (0.0, 0.0),
+            (0.0, 0.0),
+            (1.0, 1.0),
+            Some('@'),
+            false,
+        );
+        player.components.render.as_mut().unwrap().layer = 1;
+        let background = Entity::from_tuples(
+            EntityId(1),
+            (5.0, 0.0),
+            (0.0, 0.0),
+            (1.0, 1.0),
+            Some('.'),
+            false,
+        );
+
+        let instances = build(&[player, background], &[], RenderMode::Blocks);
+        let xs: Vec<f32> = instances.raw.iter().map(|i| i.model[3][0]).collect();
+        assert_eq!(xs, vec![0.5, 0.0]);
+    }
+
+    #[test]
+    fn test_hud_is_laid_out_in_pixels_from_the_top_left() {
+        let mut hud = Hud::new();
+        hud.rect(10.0, 20.0, 30.0, 40.0, cgmath::Vector3::new(1.0, 0.0, 0.0));
+        hud.set_text_scale(1.0);
+        hud.text(0.0, 0.0, "a b", cgmath::Vector3::new(0.0, 0.0, 0.0));
+
+        let mut instances = Instances::default();
+        instances.rebuild_hud(&hud, 100.0);
+        // The space is skipped.
+        assert_eq!(instances.raw.len(), 3);
+        assert_eq!(
+            instances.batches(),
+            vec![(TextureSlot::White, 0..1), (TextureSlot::Glyphs, 1..3)]
+        );
+
+        let corners: Vec<[f32; 4]> = instances
+            .raw
+            .iter()
+            .map(|i| [i.model[3][0], i.model[3][1], i.model[0][0], i.model[1][1]])
+            .collect();
+        assert_eq!(
+            corners,
+            vec![
+                [10.0, 40.0, 30.0, 40.0],
+                [0.0, 90.0, 6.0, 10.0],
+                [12.0, 90.0, 6.0, 10.0],
+            ]
+        );
     }
 }
@@ -1,19 +1,37 @@
+use crate::runtime::capture::{Capture, FrameRecorder};
+use crate::runtime::debug_draw::debug_boxes;
+use crate::runtime::gamepad::{GamepadBackend, GamepadInput};
+use crate::runtime::hud::Hud;
+use crate::runtime::inspector::Inspector;
+use crate::runtime::profiler::Profiler;
+use crate::runtime::recording::{InputEvent, InputRecorder};
 use crate::runtime::GameHandler;
 use crate::runtime::Key;
-use crate::scene::Entity;
+use crate::runtime::RuntimeState;
+use crate::runtime::TextInput;
+use crate::scene::AtlasId;
 use crate::wgpu::render::CameraStrategy;
+use crate::wgpu::render::Projection;
+use crate::wgpu::render::RenderMode;
+use crate::wgpu::render::RenderSettings;
 use crate::wgpu::render::State;
+use cgmath::Vector3;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 use winit::application::ApplicationHandler;
+use winit::dpi::LogicalSize;
+use winit::event::Ime;
 use winit::event::KeyEvent;
 use winit::event::MouseButton;
 use winit::event::WindowEvent;
 use winit::event_loop::ActiveEventLoop;
 use winit::event_loop::EventLoop;
+use winit::keyboard::KeyCode;
 use winit::keyboard::PhysicalKey;
+use winit::window::Fullscreen;
 use winit::window::Window;
 
 impl TryFrom<winit::keyboard::KeyCode> for Key {
@@ -32,14 +50,169 @@ impl TryFrom<winit::keyboard::KeyCode> for Key {
     }
 }
 
+/// Text produced by a key press while text input is enabled.
+fn text_inputs(code: KeyCode, text: Option<&str>) -> Vec<TextInput> {
+    match code {
+        KeyCode::Backspace => vec![TextInput::Backspace],
+        KeyCode::Enter | KeyCode::NumpadEnter => vec![TextInput::Enter],
+        _ => text
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !c.is_control())
+            .map(TextInput::Char)
+            .collect(),
+    }
+}
+
+/// Settings for the window the game runs in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowConfig {
+    pub title: String,
+    /// Initial inner size in logical pixels. `None` lets the platform choose.
+    pub size: Option<(u32, u32)>,
+    /// Smallest inner size the window can be resized to, in logical pixels.
+    pub min_size: Option<(u32, u32)>,
+    pub resizable: bool,
+    /// Start as a borderless fullscreen window on the current monitor.
+    pub fullscreen: bool,
+    /// Wait for the display's vertical sync before presenting each frame.
+    pub vsync: bool,
+    /// Background colour behind the scene.
+    pub clear_color: Vector3<f32>,
+    /// Close the window when Escape is pressed. Turn this off to handle Escape in the game,
+    /// e.g. to open a pause menu.
+    pub escape_quits: bool,
+    /// Id of the `<canvas>` element to draw into on the web.
+    pub canvas_id: String,
+}
+
+impl Default for WindowConfig {
+    fn default() -> WindowConfig {
+        WindowConfig {
+            title: "hewn".to_string(),
+            size: None,
+            min_size: None,
+            resizable: true,
+            fullscreen: false,
+            vsync: true,
+            clear_color: Vector3::new(1.0, 1.0, 1.0),
+            escape_quits: true,
+            canvas_id: "canvas".to_string(),
+        }
+    }
+}
+
+impl WindowConfig {
+    fn window_attributes(&self) -> winit::window::WindowAttributes {
+        let mut attributes = Window::default_attributes()
+            .with_title(self.title.clone())
+            .with_resizable(self.resizable);
+        if let Some((width, height)) = self.size {
+            attributes = attributes.with_inner_size(LogicalSize::new(width, height));
+        }
+        if let Some((width, height)) = self.min_size {
+            attributes = attributes.with_min_inner_size(LogicalSize::new(width, height));
+        }
+        if self.fullscreen {
+            attributes = attributes.with_fullscreen(Some(Fullscreen::Borderless(None)));
+        }
+        attributes
+    }
+}
+
 #[derive(Default)]
-pub struct WindowRuntime {}
+pub struct WindowRuntime {
+    #[cfg(not(target_arch = "wasm32"))]
+    recording_path: Option<PathBuf>,
+    gamepad: Option<GamepadInput>,
+    keep_running_unfocused: bool,
+    atlases: Vec<image::DynamicImage>,
+    render_mode: RenderMode,
+    projection: Projection,
+    window_config: WindowConfig,
+    capture: Capture,
+    debug_draw: bool,
+    time_scale: Option<f32>,
+    trace_path: Option<PathBuf>,
+}
 
 impl WindowRuntime {
     pub fn new() -> WindowRuntime {
         WindowRuntime::default()
     }
 
+    /// Record every input event and frame time to `path`, saved when the window closes.
+    /// Not available on the web, which has no file system.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn record_input(&mut self, path: impl Into<PathBuf>) {
+        self.recording_path = Some(path.into());
+    }
+
+    /// Poll `backend` for gamepad events every frame.
+    pub fn set_gamepad_backend(&mut self, backend: Box<dyn GamepadBackend>) {
+        self.gamepad = Some(GamepadInput::new(backend));
+    }
+
+    /// By default the game is paused while the window is unfocused, and resumes when it
+    /// regains focus unless it was already paused, e.g. with F5. Set this to keep calling
+    /// [`GameHandler::next`] in the background.
+    pub fn set_keep_running_unfocused(&mut self, keep_running: bool) {
+        self.keep_running_unfocused = keep_running;
+    }
+
+    /// Draw entities as coloured blocks (the default) or as their ASCII characters.
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        self.render_mode = render_mode;
+    }
+
+    /// Use a perspective (the default) or orthographic camera.
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    /// Save a PNG of the window into `dir` when F12 is pressed.
+    pub fn set_screenshot_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.capture.set_screenshot_dir(dir.into());
+    }
+
+    /// Record every frame, saved when the window closes.
+    pub fn record_frames(&mut self, recorder: FrameRecorder) {
+        self.capture.recorder = Some(recorder);
+    }
+
+    /// Trace every frame, written to `path` as Chrome trace JSON when the window closes.
+    pub fn save_chrome_trace(&mut self, path: impl Into<PathBuf>) {
+        self.trace_path = Some(path.into());
+    }
+
+    /// Scale `dt` for slow motion or fast forward, between 1/16 and 16. F7 and F8 halve and
+    /// double it. A value that isn't finite is logged and ignored.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = Some(time_scale);
+    }
+
+    /// Draw collision boxes, velocities and entity ids over the scene. Toggled with F3.
+    pub fn set_debug_draw(&mut self, enabled: bool) {
+        self.debug_draw = enabled;
+    }
+
+    /// Set the window title, size, fullscreen, vsync, background colour and so on.
+    pub fn set_window_config(&mut self, window_config: WindowConfig) {
+        self.window_config = window_config;
+    }
+
+    /// Add a texture atlas for [`SpriteComponent`](crate::scene::SpriteComponent)s to
+    /// reference. Atlases are numbered in the order they are added.
+    pub fn add_atlas(&mut self, image: image::DynamicImage) -> AtlasId {
+        self.atlases.push(image);
+        AtlasId(self.atlases.len() as u16 - 1)
+    }
+
+    /// Decode an encoded image, e.g. from `include_bytes!`, and add it as an atlas.
+    pub fn add_atlas_from_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<AtlasId> {
+        Ok(self.add_atlas(image::load_from_memory(bytes)?))
+    }
+
     pub fn start(
         &mut self,
         game: &mut dyn GameHandler,
@@ -61,9 +234,50 @@ impl WindowRuntime {
             game,
             camera_strategy,
         );
-        event_loop.run_app(&mut app)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            app.recorder = self.recording_path.clone().map(InputRecorder::new);
+        }
+        app.gamepad = self.gamepad.take();
+        app.pause_on_focus_loss = !self.keep_running_unfocused;
+        let clear_color = self.window_config.clear_color;
+        app.render_settings = RenderSettings {
+            camera_strategy,
+            render_mode: self.render_mode,
+            projection: self.projection,
+            atlases: self.atlases.clone(),
+            clear_color: wgpu::Color {
+                r: clear_color.x as f64,
+                g: clear_color.y as f64,
+                b: clear_color.z as f64,
+                a: 1.0,
+            },
+            vsync: self.window_config.vsync,
+        };
+        app.window_config = self.window_config.clone();
+        app.capture = std::mem::take(&mut self.capture);
+        app.debug_draw = self.debug_draw;
+        if self.trace_path.is_some() {
+            app.profiler.start_trace();
+        }
+        if let Some(time_scale) = self.time_scale {
+            if let Err(e) = app.runtime_state.set_time_scale(time_scale) {
+                log::error!("{:#}", e);
+            }
+        }
+        let mut results = vec![event_loop.run_app(&mut app).map_err(anyhow::Error::from)];
 
-        Ok(())
+        // Finish every recording before reporting the first error, so one failing doesn't
+        // lose the others.
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(recorder) = &app.recorder {
+            results.push(recorder.save());
+        }
+        results.push(app.capture.finish());
+        if let Some(path) = &self.trace_path {
+            results.push(app.profiler.save_chrome_trace(path));
+        }
+        results.into_iter().collect()
     }
 }
 
@@ -72,8 +286,20 @@ pub struct App<'a> {
     pub(crate) proxy: Option<winit::event_loop::EventLoopProxy<State>>,
     pub(crate) render_state: Option<State>,
     pub(crate) game: &'a mut dyn GameHandler,
-    pub(crate) frame_counter: u32,
-    pub(crate) camera_strategy: CameraStrategy,
+    pub(crate) recorder: Option<InputRecorder>,
+    pub(crate) gamepad: Option<GamepadInput>,
+    text_input_enabled: bool,
+    ime_composing: bool,
+    runtime_state: RuntimeState,
+    pause_on_focus_loss: bool,
+    render_settings: RenderSettings,
+    window_config: WindowConfig,
+    hud: Hud,
+    capture: Capture,
+    debug_draw: bool,
+    inspector: Inspector,
+    cursor_position: Option<(f32, f32)>,
+    profiler: Profiler,
 
     last_frame: Instant,
 }
@@ -91,53 +317,64 @@ impl<'a> App<'a> {
             #[cfg(target_arch = "wasm32")]
             proxy,
             game,
-            frame_counter: 0,
-            camera_strategy,
+            profiler: Profiler::new(),
+            recorder: None,
+            gamepad: None,
+            text_input_enabled: false,
+            ime_composing: false,
+            runtime_state: RuntimeState::default(),
+            pause_on_focus_loss: true,
+            render_settings: RenderSettings {
+                camera_strategy,
+                ..RenderSettings::default()
+            },
+            window_config: WindowConfig::default(),
+            hud: Hud::new(),
+            capture: Capture::default(),
+            debug_draw: false,
+            inspector: Inspector::default(),
+            cursor_position: None,
             last_frame: std::time::Instant::now(),
         }
     }
 }
 
+fn dispatch(game: &mut dyn GameHandler, recorder: &mut Option<InputRecorder>, event: InputEvent) {
+    if let Some(recorder) = recorder {
+        recorder.record(event);
+    }
+    event.apply(game);
+}
+
 impl<'a> ApplicationHandler<State> for App<'a> {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        self.runtime_state.set_paused(self.game, false);
+
         #[allow(unused_mut)]
-        let mut window_attributes = Window::default_attributes();
+        let mut window_attributes = self.window_config.window_attributes();
 
         #[cfg(target_arch = "wasm32")]
         {
             use wasm_bindgen::JsCast;
             use winit::platform::web::WindowAttributesExtWebSys;
 
-            const CANVAS_ID: &str = "canvas";
-
             let window = wgpu::web_sys::window().unwrap_throw();
             let document = window.document().unwrap_throw();
-            let canvas = document.get_element_by_id(CANVAS_ID).unwrap_throw();
+            let canvas = document
+                .get_element_by_id(&self.window_config.canvas_id)
+                .unwrap_throw();
             let html_canvas_element = canvas.unchecked_into();
             window_attributes = window_attributes.with_canvas(Some(html_canvas_element));
         }
 
-        let renderable_entities = self
-            .game
-            .scene()
-            .get_entities_with_component(crate::scene::ComponentType::Render)
-            .iter()
-            .map(|e| **e)
-            // probably terrible performance cloning here we when we should pass a reference as we only
-            // need to read - but this is a temporary fix for now.
-            .collect::<Vec<Entity>>();
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+        #[cfg(target_arch = "wasm32")]
+        let settings = self.render_settings.clone();
 
         #[cfg(not(target_arch = "wasm32"))]
         {
-            self.render_state = Some(
-                pollster::block_on(State::new(
-                    window,
-                    renderable_entities,
-                    self.camera_strategy,
-                ))
-                .unwrap(),
-            );
+            self.render_state =
+                Some(pollster::block_on(State::new(window, self.render_settings.clone())).unwrap());
         }
 
         #[cfg(target_arch = "wasm32")]
@@ -146,7 +383,7 @@ impl<'a> ApplicationHandler<State> for App<'a> {
                 wasm_bindgen_futures::spawn_local(async move {
                     assert!(proxy
                         .send_event(
-                            State::new(window, renderable_entities, self.camera_strategy,)
+                            State::new(window, settings)
                                 .await
                                 .expect("Unable to create canvas!!!")
                         )
@@ -156,6 +393,14 @@ impl<'a> ApplicationHandler<State> for App<'a> {
         }
     }
 
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        self.runtime_state.set_paused(self.game, true);
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        self.game.on_exit();
+    }
+
     #[allow(unused_mut)]
     fn user_event(&mut self, _event_loop: &ActiveEventLoop, mut event: State) {
         #[cfg(target_arch = "wasm32")]
@@ -182,28 +427,84 @@ impl<'a> ApplicationHandler<State> for App<'a> {
 
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
-            WindowEvent::Resized(size) => state.resize(size.width, size.height),
+            WindowEvent::Resized(size) => {
+                state.resize(size.width, size.height);
+                self.game.on_resize(size.width, size.height);
+            }
+            WindowEvent::Focused(focused) => {
+                self.game.on_focus_changed(focused);
+                if self.pause_on_focus_loss {
+                    self.runtime_state.set_focused(self.game, focused);
+                }
+            }
             WindowEvent::RedrawRequested => {
-                self.frame_counter += 1;
-
                 let now = Instant::now();
                 let dt = now - self.last_frame;
-                self.game.next(dt);
-                self.frame_counter = 0;
+                self.profiler.record_frame(dt);
+                if let Some(game_dt) = self.runtime_state.next_dt(self.game, dt) {
+                    let gamepad_events = self
+                        .gamepad
+                        .as_mut()
+                        .map(GamepadInput::poll)
+                        .unwrap_or_default();
+                    for event in gamepad_events {
+                        dispatch(self.game, &mut self.recorder, event);
+                    }
+                    let update_start = Instant::now();
+                    self.game.next_profiled(game_dt, &mut self.profiler);
+                    self.profiler
+                        .record("update", update_start, update_start.elapsed());
+                    if let Some(recorder) = &mut self.recorder {
+                        recorder.end_frame(game_dt);
+                    }
+                }
+                if self.game.should_exit() {
+                    event_loop.exit();
+                    return;
+                }
+                let text_input_enabled = self.game.text_input_enabled();
+                if text_input_enabled != self.text_input_enabled {
+                    state.window.set_ime_allowed(text_input_enabled);
+                    self.text_input_enabled = text_input_enabled;
+                }
                 self.last_frame = now;
 
-                let renderable_entities = self
-                    .game
-                    .scene()
-                    .get_entities_with_component(crate::scene::ComponentType::Render)
-                    .iter()
-                    .map(|e| **e)
-                    // probably terrible performance cloning here we when we should pass a reference as we only
-                    // need to read - but this is a quick fix for now.
-                    .collect::<Vec<Entity>>();
-                state.update(renderable_entities);
-                match state.render() {
-                    Ok(_) => {}
+                let prep_start = Instant::now();
+                state.update(&self.game.scenes(), self.game.camera(), dt);
+                let boxes = if self.debug_draw {
+                    debug_boxes(self.game.scene(), self.runtime_state.game_dt())
+                } else {
+                    vec![]
+                };
+                state.update_debug(&boxes);
+                self.hud.clear();
+                self.game.hud(&mut self.hud);
+                self.hud
+                    .add_debug_labels(&boxes, |x, y| state.world_to_screen(x, y));
+                if self.inspector.is_open() {
+                    self.inspector.draw(&mut self.hud, self.game.scene());
+                }
+                if let Some(status) = self.runtime_state.status() {
+                    self.hud.add_status(&status, state.size().0 as f32);
+                }
+                if let Some(debug) = self.game.debug_str() {
+                    self.hud.add_debug_lines(&debug, state.size().1 as f32);
+                }
+                state.update_hud(&self.hud);
+                self.profiler
+                    .record("render-prep", prep_start, prep_start.elapsed());
+                let wants_frame = self.capture.wants_frame();
+                let rendered = self
+                    .profiler
+                    .time("gpu-submit", || state.render(wants_frame));
+                self.game.on_profile(&self.profiler);
+                match rendered {
+                    Ok(Some(frame)) => {
+                        if let Err(e) = self.capture.add_frame(frame, dt) {
+                            log::error!("Unable to capture frame {:#}", e);
+                        }
+                    }
+                    Ok(None) => {}
                     // Reconfigure the surface if it's lost or outdated
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
                         let size = state.window.inner_size();
@@ -214,9 +515,22 @@ impl<'a> ApplicationHandler<State> for App<'a> {
                     }
                 }
             }
-            WindowEvent::MouseInput { state, button, .. } => match (button, state.is_pressed()) {
-                (MouseButton::Left, true) => {}
-                (MouseButton::Left, false) => {}
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Some((position.x as f32, position.y as f32));
+            }
+            WindowEvent::MouseInput {
+                state: button_state,
+                button,
+                ..
+            } => match (button, button_state.is_pressed()) {
+                (MouseButton::Left, true) if self.inspector.is_open() => {
+                    let world = self
+                        .cursor_position
+                        .and_then(|(x, y)| state.screen_to_world(x, y));
+                    if let Some((x, y)) = world {
+                        self.inspector.select_at(self.game.scene(), x, y);
+                    }
+                }
                 _ => {}
             },
             WindowEvent::KeyboardInput {
@@ -224,14 +538,88 @@ impl<'a> ApplicationHandler<State> for App<'a> {
                     KeyEvent {
                         physical_key: PhysicalKey::Code(code),
                         state: key_state,
+                        text,
                         ..
                     },
                 ..
             } => {
-                state.handle_key(event_loop, code, key_state.is_pressed());
-                let _ = code
-                    .try_into()
-                    .and_then(|key| Ok(self.game.handle_key(key, key_state.is_pressed())));
+                if self.window_config.escape_quits
+                    && code == KeyCode::Escape
+                    && key_state.is_pressed()
+                {
+                    event_loop.exit();
+                }
+                if code == KeyCode::F12 && key_state.is_pressed() {
+                    self.capture.request_screenshot();
+                }
+                if code == KeyCode::F3 && key_state.is_pressed() {
+                    self.debug_draw = !self.debug_draw;
+                }
+                let function_key = match code {
+                    KeyCode::F5 => 5,
+                    KeyCode::F6 => 6,
+                    KeyCode::F7 => 7,
+                    KeyCode::F8 => 8,
+                    _ => 0,
+                };
+                if key_state.is_pressed()
+                    && self
+                        .runtime_state
+                        .handle_function_key(self.game, function_key)
+                {
+                    return;
+                }
+                if code == KeyCode::F1 && key_state.is_pressed() {
+                    self.inspector.toggle();
+                    return;
+                }
+                // While the inspector is open, key presses go to its console.
+                if self.inspector.is_open() && key_state.is_pressed() {
+                    match code {
+                        KeyCode::ArrowUp => self.inspector.select_next(self.game.scene(), -1),
+                        KeyCode::ArrowDown => self.inspector.select_next(self.game.scene(), 1),
+                        _ => {
+                            for input in text_inputs(code, text.as_deref()) {
+                                self.inspector.handle_text(
+                                    input,
+                                    self.game,
+                                    &mut self.runtime_state,
+                                );
+                            }
+                        }
+                    }
+                    return;
+                }
+                let text_input_enabled = self.game.text_input_enabled();
+                if text_input_enabled && key_state.is_pressed() {
+                    // Characters typed mid-composition arrive through `Ime::Commit` instead.
+                    let text = text.as_deref().filter(|_| !self.ime_composing);
+                    for input in text_inputs(code, text) {
+                        dispatch(self.game, &mut self.recorder, InputEvent::Text(input));
+                    }
+                }
+                if let Ok(key) = Key::try_from(code) {
+                    if text_input_enabled && key == Key::Space {
+                        return;
+                    }
+                    let event = InputEvent::Key {
+                        key,
+                        pressed: key_state.is_pressed(),
+                    };
+                    dispatch(self.game, &mut self.recorder, event);
+                }
+            }
+            WindowEvent::Ime(Ime::Preedit(text, _)) => {
+                self.ime_composing = !text.is_empty();
+            }
+            WindowEvent::Ime(Ime::Commit(text)) => {
+                self.ime_composing = false;
+                if self.game.text_input_enabled() {
+                    for c in text.chars() {
+                        let event = InputEvent::Text(TextInput::Char(c));
+                        dispatch(self.game, &mut self.recorder, event);
+                    }
+                }
             }
             _ => {}
         }
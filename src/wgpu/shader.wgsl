@@ -1,12 +1,11 @@
-struct InstancePositionInput {
+struct InstanceInput {
     @location(5) model_matrix_0: vec4<f32>,
     @location(6) model_matrix_1: vec4<f32>,
     @location(7) model_matrix_2: vec4<f32>,
     @location(8) model_matrix_3: vec4<f32>,
-};
-
-struct InstanceColorInput {
-    @location(9) model_matrix_0: vec3<f32>,
+    @location(9) color: vec3<f32>,
+    // Top-left and bottom-right texture coordinates of the instance's region.
+    @location(10) uv: vec4<f32>,
 };
 
 
@@ -24,16 +23,16 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
 };
 
 @vertex
 fn vs_main(
     model: VertexInput,
-    instance: InstancePositionInput,
-    instance_color: InstanceColorInput,
+    instance: InstanceInput,
 ) -> VertexOutput {
     var out: VertexOutput;
-    let color = instance_color.model_matrix_0;
+    let color = instance.color;
     let model_matrix = mat4x4<f32>(
         instance.model_matrix_0,
         instance.model_matrix_1,
@@ -41,17 +40,23 @@ fn vs_main(
         instance.model_matrix_3,
     );
     out.color = color;
+    // The quad's y axis points up but texture rows run downwards.
+    out.tex_coords = mix(
+        instance.uv.xy,
+        instance.uv.zw,
+        vec2<f32>(model.position.x, 1.0 - model.position.y),
+    );
     out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
     return out;
 }
 
-// @group(0) @binding(0)
-// var t_diffuse: texture_2d<f32>;
-// @group(0) @binding(1)
-// var s_diffuse: sampler;
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
+    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * vec4<f32>(in.color, 1.0);
 }
  
\ No newline at end of file